@@ -0,0 +1,23 @@
+use std::process::Command;
+
+/// Exposes the commit being built as `GIT_COMMIT`, unless it is already set, e.g. by a
+/// Docker build without the `.git` directory, and rebuilds the embedded migrations.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=GIT_COMMIT={}",
+        commit.unwrap_or_else(|| "unknown".to_string())
+    );
+}
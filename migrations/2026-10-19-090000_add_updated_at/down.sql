@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS set_updated_at ON crates;
+DROP TRIGGER IF EXISTS set_updated_at ON rustaceans;
+
+ALTER TABLE crates DROP COLUMN updated_at;
+ALTER TABLE rustaceans DROP COLUMN updated_at;
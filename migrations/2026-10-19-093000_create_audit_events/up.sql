@@ -0,0 +1,14 @@
+CREATE TABLE audit_events (
+    id SERIAL PRIMARY KEY,
+    actor_id integer,
+    entity varchar(64) NOT NULL,
+    entity_id integer NOT NULL,
+    action varchar(16) NOT NULL,
+    before jsonb,
+    after jsonb,
+    request_id varchar(64),
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX audit_events_entity_idx ON audit_events (entity, entity_id);
+CREATE INDEX audit_events_created_at_idx ON audit_events (created_at);
@@ -0,0 +1,10 @@
+CREATE TABLE digest_subscriptions (
+    id SERIAL PRIMARY KEY,
+    user_id integer NOT NULL REFERENCES users(id),
+    email varchar(254) NOT NULL,
+    frequency varchar(16) NOT NULL,
+    rustacean_id integer REFERENCES rustaceans(id) ON DELETE CASCADE,
+    keyword varchar(128),
+    last_sent_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+)
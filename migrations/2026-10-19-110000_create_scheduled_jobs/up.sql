@@ -0,0 +1,7 @@
+CREATE TABLE scheduled_jobs (
+    name varchar(64) PRIMARY KEY,
+    last_started_at TIMESTAMP NOT NULL,
+    last_finished_at TIMESTAMP,
+    last_status varchar(16) NOT NULL,
+    last_error TEXT
+)
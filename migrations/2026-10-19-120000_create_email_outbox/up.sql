@@ -0,0 +1,15 @@
+CREATE TABLE email_outbox (
+    id SERIAL PRIMARY KEY,
+    sender varchar(254),
+    recipients text[] NOT NULL,
+    subject text,
+    message bytea NOT NULL,
+    status varchar(16) NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP DEFAULT NOW() NOT NULL,
+    sent_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX email_outbox_pending_idx ON email_outbox (next_attempt_at) WHERE status = 'pending';
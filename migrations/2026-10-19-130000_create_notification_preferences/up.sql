@@ -0,0 +1,8 @@
+CREATE TABLE notification_preferences (
+    user_id integer PRIMARY KEY REFERENCES users(id),
+    email varchar(254) NOT NULL,
+    crate_updated boolean NOT NULL DEFAULT TRUE,
+    crate_deleted boolean NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX notification_preferences_email_idx ON notification_preferences (lower(email));
@@ -0,0 +1,25 @@
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    url varchar(2048) NOT NULL,
+    events text[] NOT NULL DEFAULT '{}',
+    secret varchar(128) NOT NULL,
+    active boolean NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+);
+
+CREATE TABLE webhook_deliveries (
+    id SERIAL PRIMARY KEY,
+    webhook_id integer NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event varchar(64) NOT NULL,
+    payload jsonb NOT NULL,
+    status varchar(16) NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    response_status integer,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP DEFAULT NOW() NOT NULL,
+    delivered_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id);
@@ -0,0 +1,3 @@
+DELETE FROM roles
+WHERE code IN ('admin', 'editor', 'viewer')
+    AND id NOT IN (SELECT role_id FROM user_roles);
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Canonical roles, renaming the ones created on the fly under their code
+INSERT INTO roles (code, name) VALUES
+    ('admin', 'Admin'),
+    ('editor', 'Editor'),
+    ('viewer', 'Viewer')
+ON CONFLICT (code) DO UPDATE SET name = EXCLUDED.name WHERE roles.name = roles.code;
@@ -0,0 +1,33 @@
+CREATE TABLE permissions (
+    id SERIAL PRIMARY KEY,
+    code varchar(64) NOT NULL UNIQUE,
+    description varchar(256) NOT NULL,
+    created_at TIMESTAMP DEFAULT NOW() NOT NULL
+);
+
+CREATE TABLE role_permissions (
+    id SERIAL PRIMARY KEY,
+    role_id integer NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    permission_id integer NOT NULL REFERENCES permissions(id) ON DELETE CASCADE,
+    UNIQUE (role_id, permission_id)
+);
+
+INSERT INTO permissions (code, description) VALUES
+    ('crate:create', 'Publish crates'),
+    ('crate:update', 'Edit crates'),
+    ('crate:delete', 'Delete crates'),
+    ('rustacean:create', 'Register rustaceans'),
+    ('rustacean:update', 'Edit rustaceans'),
+    ('rustacean:delete', 'Delete rustaceans'),
+    ('audit:read', 'Read the audit log'),
+    ('digest:preview', 'Preview the digest email'),
+    ('job:read', 'Read the scheduled jobs status'),
+    ('webhook:manage', 'Manage webhooks and their deliveries'),
+    ('user:manage', 'Manage roles and their permissions');
+
+-- Same access as the role checks these permissions replace
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE roles.code = 'admin'
+    OR (roles.code = 'editor' AND permissions.code LIKE ANY (ARRAY['crate:%', 'rustacean:%']));
@@ -0,0 +1 @@
+ALTER TABLE notification_preferences ADD COLUMN email_verified_at timestamp;
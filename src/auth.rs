@@ -1,12 +1,16 @@
+use crate::config;
 use crate::models::User;
 use argon2::{
     password_hash::{Error, SaltString},
     PasswordHash, PasswordHasher, PasswordVerifier,
 };
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
 use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use sha2::Sha256;
 
-pub const SESSION_LIFE_TIME: usize = 3 * 60 * 60;
 pub const SESSION_ID_LENGTH: usize = 128;
+pub const EMAIL_VERIFICATION_HOURS: i64 = 48;
 
 #[derive(serde::Deserialize)]
 pub struct Credentials {
@@ -14,10 +18,14 @@ pub struct Credentials {
     pub password: String,
 }
 
-pub fn authorize_user(user: &User, credentials: &Credentials) -> Result<String, Error> {
+pub fn verify_password(user: &User, password: &str) -> Result<(), Error> {
     let db_hash = PasswordHash::new(&user.password)?;
     let argon = argon2::Argon2::default();
-    argon.verify_password(credentials.password.as_bytes(), &db_hash)?;
+    argon.verify_password(password.as_bytes(), &db_hash)
+}
+
+pub fn authorize_user(user: &User, credentials: &Credentials) -> Result<String, Error> {
+    verify_password(user, &credentials.password)?;
 
     let sesssion_id = rand::thread_rng()
         .sample_iter(&Alphanumeric)
@@ -34,3 +42,149 @@ pub fn hash_password(password: String) -> Result<String, Error> {
     let password_hash = argon.hash_password(password.as_bytes(), &salt)?;
     Ok(password_hash.to_string())
 }
+
+#[derive(Debug)]
+pub enum TokenError {
+    MissingSecret,
+    Invalid,
+    Expired,
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::MissingSecret => write!(f, "Missing signing_secret (SIGNING_SECRET)"),
+            TokenError::Invalid => write!(f, "Invalid token"),
+            TokenError::Expired => write!(f, "Expired token"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Signature of `{timestamp}.{payload}`, sent as `sha256={hex}` so receivers can verify
+/// it with the secret and reject replays of old timestamps.
+pub fn sign_webhook_payload(secret: &str, timestamp: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn signing_mac(message: &str) -> Result<Hmac<Sha256>, TokenError> {
+    let secret = config::get()
+        .signing_secret
+        .as_ref()
+        .ok_or(TokenError::MissingSecret)?;
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| TokenError::MissingSecret)?;
+    mac.update(message.as_bytes());
+    Ok(mac)
+}
+
+/// Splits a `{id}.{expires_at}.{hmac}` token, failing on any other shape.
+fn split_expiring_token(token: &str) -> Result<(i32, i64, Vec<u8>), TokenError> {
+    let mut parts = token.splitn(3, '.');
+    let (Some(id), Some(expires_at), Some(signature)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(TokenError::Invalid);
+    };
+    let id = id.parse().map_err(|_| TokenError::Invalid)?;
+    let expires_at = expires_at.parse().map_err(|_| TokenError::Invalid)?;
+    let signature = hex::decode(signature).map_err(|_| TokenError::Invalid)?;
+    Ok((id, expires_at, signature))
+}
+
+/// Checks the signature of an expiring token before its expiry, so forged timestamps
+/// are reported as invalid rather than expired.
+fn verify_expiring_token(
+    mac: Hmac<Sha256>,
+    expires_at: i64,
+    signature: &[u8],
+) -> Result<(), TokenError> {
+    mac.verify_slice(signature)
+        .map_err(|_| TokenError::Invalid)?;
+    if expires_at < Utc::now().timestamp() {
+        return Err(TokenError::Expired);
+    }
+    Ok(())
+}
+
+fn unsubscribe_mac(subscription_id: i32, expires_at: i64) -> Result<Hmac<Sha256>, TokenError> {
+    signing_mac(&format!("unsubscribe:{}:{}", subscription_id, expires_at))
+}
+
+/// Signs a `{id}.{expires_at}.{hmac}` token that unsubscribes from a digest subscription
+/// without logging in, valid for `digest.unsubscribe_token_days`.
+pub fn sign_unsubscribe_token(subscription_id: i32) -> Result<String, TokenError> {
+    let days = config::get().digest.unsubscribe_token_days;
+    let expires_at = (Utc::now() + Duration::days(days.into())).timestamp();
+    let signature = unsubscribe_mac(subscription_id, expires_at)?
+        .finalize()
+        .into_bytes();
+    Ok(format!(
+        "{}.{}.{}",
+        subscription_id,
+        expires_at,
+        hex::encode(signature)
+    ))
+}
+
+/// Returns the subscription id of a token issued by `sign_unsubscribe_token`.
+pub fn verify_unsubscribe_token(token: &str) -> Result<i32, TokenError> {
+    let (subscription_id, expires_at, signature) = split_expiring_token(token)?;
+    verify_expiring_token(
+        unsubscribe_mac(subscription_id, expires_at)?,
+        expires_at,
+        &signature,
+    )?;
+    Ok(subscription_id)
+}
+
+fn email_verification_mac(
+    user_id: i32,
+    email: &str,
+    expires_at: i64,
+) -> Result<Hmac<Sha256>, TokenError> {
+    signing_mac(&format!(
+        "verify-email:{}:{}:{}",
+        user_id,
+        email.to_lowercase(),
+        expires_at
+    ))
+}
+
+/// Signs a `{user_id}.{expires_at}.{hmac}` token proving that the user receives mail at
+/// `email`. It stops working once the user saves another email.
+pub fn sign_email_verification_token(user_id: i32, email: &str) -> Result<String, TokenError> {
+    let expires_at = (Utc::now() + Duration::hours(EMAIL_VERIFICATION_HOURS)).timestamp();
+    let signature = email_verification_mac(user_id, email, expires_at)?
+        .finalize()
+        .into_bytes();
+    Ok(format!(
+        "{}.{}.{}",
+        user_id,
+        expires_at,
+        hex::encode(signature)
+    ))
+}
+
+/// Returns the user a verification token was issued to, before its signature is checked.
+pub fn email_verification_user_id(token: &str) -> Result<i32, TokenError> {
+    let (user_id, _) = token.split_once('.').ok_or(TokenError::Invalid)?;
+    user_id.parse().map_err(|_| TokenError::Invalid)
+}
+
+/// Checks a token issued by `sign_email_verification_token` against the current email
+/// of its user and returns the user id.
+pub fn verify_email_verification_token(token: &str, email: &str) -> Result<i32, TokenError> {
+    let (user_id, expires_at, signature) = split_expiring_token(token)?;
+    verify_expiring_token(
+        email_verification_mac(user_id, email, expires_at)?,
+        expires_at,
+        &signature,
+    )?;
+    Ok(user_id)
+}
@@ -1,7 +1,9 @@
-use clap::{arg, Arg, Command};
+use clap::{arg, Arg, ArgAction, Command};
 
 extern crate cr8s;
 
+const DRY_RUN_HOURS_SINCE: i32 = 24;
+
 fn main() {
     let matches = Command::new("Cr8s")
         .about("Cr8s CLI")
@@ -32,18 +34,125 @@ fn main() {
                     ),
                 ),
         )
+        .subcommand(
+            Command::new("roles")
+                .about("Cr8s role management")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("list").about("List all available roles"))
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a role, its code must be a known role code")
+                        .arg_required_else_help(true)
+                        .arg(Arg::new("code").required(true))
+                        .arg(Arg::new("name").required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Cr8s audit log")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("tail")
+                        .about("Print the most recent audit events")
+                        .arg(
+                            Arg::new("limit")
+                                .long("limit")
+                                .default_value("20")
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("outbox")
+                .about("Cr8s outbound email queue")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("deliver")
+                        .about("Deliver every due email")
+                        .arg(
+                            Arg::new("to")
+                                .long("to")
+                                .help("Only deliver the emails to this recipient"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the most recent emails")
+                        .arg(
+                            Arg::new("status")
+                                .long("status")
+                                .value_parser(["pending", "sent", "dead"]),
+                        )
+                        .arg(
+                            Arg::new("limit")
+                                .long("limit")
+                                .default_value("20")
+                                .value_parser(clap::value_parser!(i64)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("requeue")
+                        .about("Requeue a failed email by ID, or every dead-lettered email")
+                        .arg(Arg::new("id").value_parser(clap::value_parser!(i32)))
+                        .arg(
+                            Arg::new("all")
+                                .long("all")
+                                .action(ArgAction::SetTrue)
+                                .conflicts_with("id"),
+                        )
+                        .group(
+                            clap::ArgGroup::new("target")
+                                .args(["id", "all"])
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("webhooks")
+                .about("Cr8s outgoing webhooks")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("deliver").about("Deliver every due webhook call")),
+        )
+        .subcommand(
+            Command::new("db")
+                .about("Cr8s database migrations")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("migrate").about("Apply every pending migration"))
+                .subcommand(Command::new("rollback").about("Revert the last applied migration"))
+                .subcommand(Command::new("status").about("List migrations and whether they are applied"))
+                .subcommand(
+                    Command::new("seed")
+                        .about("Create the roles, users, rustaceans and crates of a profile, skipping existing ones")
+                        .arg(
+                            Arg::new("profile")
+                                .long("profile")
+                                .default_value("demo")
+                                .value_parser(["demo", "test"]),
+                        ),
+                )
+                .subcommand(
+                    Command::new("reset")
+                        .about("Revert and reapply every migration, deleting all data")
+                        .arg(
+                            Arg::new("yes")
+                                .long("yes")
+                                .help("Confirm that all data is deleted")
+                                .action(ArgAction::SetTrue)
+                                .required(true),
+                        ),
+                ),
+        )
         .subcommand(
             Command::new("digest-send")
                 .about("Send an email with the  newest crates")
                 .arg(
                     Arg::new("to")
-                        .required(true)
-                        .num_args(1..2)
+                        .required_unless_present_any(["subscribers", "dry_run"])
                         .value_delimiter(','),
                 )
                 .arg(
                     Arg::new("hours_since")
-                        .required(true)
+                        .required_unless_present_any(["subscribers", "dry_run"])
                         .value_parser(clap::value_parser!(i32)),
                 )
                 .arg(
@@ -51,10 +160,41 @@ fn main() {
                         .required(false)
                         .default_value(None)
                         .value_parser(clap::value_parser!(Option<String>)),
+                )
+                .arg(
+                    Arg::new("subscribers")
+                        .long("subscribers")
+                        .help("Send to every digest subscription instead of the given recipients")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["to", "hours_since"]),
+                )
+                .arg(
+                    Arg::new("frequency")
+                        .long("frequency")
+                        .requires("subscribers")
+                        .value_parser(["daily", "weekly"]),
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .help("Render the digest HTML instead of sending it, hours_since defaults to 24")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("subscribers"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("File the rendered digest is written to, stdout by default")
+                        .requires("dry_run"),
                 ),
         )
         .get_matches();
 
+    if let Err(e) = cr8s::config::init(&cr8s::config::figment()) {
+        eprintln!("Invalid configuration:\n{}", e);
+        std::process::exit(1);
+    }
+
     match matches.subcommand() {
         Some(("users", sub_matches)) => match sub_matches.subcommand() {
             Some(("create", sub_matches)) => cr8s::commands::create_user(
@@ -78,6 +218,69 @@ fn main() {
             }
             _ => {}
         },
+        Some(("roles", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", _)) => cr8s::commands::roles_list(),
+            Some(("create", sub_matches)) => cr8s::commands::roles_create(
+                sub_matches.get_one::<String>("code").unwrap().to_owned(),
+                sub_matches.get_one::<String>("name").unwrap().to_owned(),
+            ),
+            _ => {}
+        },
+        Some(("audit", sub_matches)) => {
+            if let Some(("tail", sub_matches)) = sub_matches.subcommand() {
+                cr8s::commands::audit_tail(sub_matches.get_one::<i64>("limit").unwrap().to_owned())
+            }
+        }
+        Some(("outbox", sub_matches)) => match sub_matches.subcommand() {
+            Some(("deliver", sub_matches)) => cr8s::commands::outbox_deliver(
+                sub_matches.get_one::<String>("to").map(String::as_str),
+            ),
+            Some(("list", sub_matches)) => cr8s::commands::outbox_list(
+                sub_matches
+                    .get_one::<String>("status")
+                    .map(|v| v.parse().unwrap()),
+                sub_matches.get_one::<i64>("limit").unwrap().to_owned(),
+            ),
+            Some(("requeue", sub_matches)) => {
+                cr8s::commands::outbox_requeue(sub_matches.get_one::<i32>("id").copied())
+            }
+            _ => {}
+        },
+        Some(("webhooks", sub_matches)) => {
+            if let Some(("deliver", _)) = sub_matches.subcommand() {
+                cr8s::commands::webhooks_deliver()
+            }
+        }
+        Some(("db", sub_matches)) => match sub_matches.subcommand() {
+            Some(("migrate", _)) => cr8s::commands::db_migrate(),
+            Some(("rollback", _)) => cr8s::commands::db_rollback(),
+            Some(("status", _)) => cr8s::commands::db_status(),
+            Some(("reset", _)) => cr8s::commands::db_reset(),
+            Some(("seed", sub_matches)) => cr8s::commands::db_seed(
+                sub_matches
+                    .get_one::<String>("profile")
+                    .map(|v| v.parse().unwrap())
+                    .unwrap(),
+            ),
+            _ => {}
+        },
+        Some(("digest-send", sub_matches)) if sub_matches.get_flag("subscribers") => {
+            cr8s::commands::send_subscriber_digests(
+                sub_matches
+                    .get_one::<String>("frequency")
+                    .map(|v| v.parse().unwrap()),
+                sub_matches.get_one::<String>("subject").cloned(),
+            )
+        }
+        Some(("digest-send", sub_matches)) if sub_matches.get_flag("dry_run") => {
+            cr8s::commands::preview_digest(
+                sub_matches
+                    .get_one::<i32>("hours_since")
+                    .copied()
+                    .unwrap_or(DRY_RUN_HOURS_SINCE),
+                sub_matches.get_one::<String>("output").cloned(),
+            )
+        }
         Some(("digest-send", sub_matches)) => cr8s::commands::send_digest(
             sub_matches
                 .get_many::<String>("to")
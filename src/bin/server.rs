@@ -1,31 +1,90 @@
+use std::sync::Arc;
+
+use cr8s::mail::HtmlMailer;
+use cr8s::rocket_routes::Mailer;
 use rocket_db_pools::Database;
 
 extern crate cr8s;
 
 #[rocket::main]
 async fn main() {
-    let _ = rocket::build()
+    let figment = cr8s::config::figment();
+    // Building the mailer up front fails the server on missing SMTP settings or broken
+    // templates rather than on the first email.
+    let mailer = cr8s::config::init(&figment)
+        .and_then(|_| HtmlMailer::from_config().map_err(|e| format!("mail: {}", e)))
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid configuration:\n{}", e);
+            std::process::exit(1);
+        });
+
+    let _ = rocket::custom(figment)
         .mount(
             "/",
             rocket::routes![
-                cr8s::rocket_routes::options,
+                cr8s::rocket_routes::cors::preflight,
+                cr8s::rocket_routes::health::health,
+                cr8s::rocket_routes::health::ready,
+                cr8s::rocket_routes::health::version,
+                cr8s::rocket_routes::metrics::get_metrics,
+                cr8s::rocket_routes::audit::get_audit_events,
                 cr8s::rocket_routes::authorization::login,
                 cr8s::rocket_routes::authorization::me,
                 cr8s::rocket_routes::crates::get_crates,
                 cr8s::rocket_routes::crates::view_crate,
                 cr8s::rocket_routes::crates::create_crate,
                 cr8s::rocket_routes::crates::update_crate,
+                cr8s::rocket_routes::crates::patch_crate,
                 cr8s::rocket_routes::crates::delete_crate,
+                cr8s::rocket_routes::digest::preview_digest,
+                cr8s::rocket_routes::jobs::get_jobs,
+                cr8s::rocket_routes::roles::get_roles,
+                cr8s::rocket_routes::permissions::get_permissions,
+                cr8s::rocket_routes::permissions::get_role_permissions,
+                cr8s::rocket_routes::permissions::update_role_permissions,
                 cr8s::rocket_routes::rustaceans::get_rustaceans,
                 cr8s::rocket_routes::rustaceans::view_rustacean,
                 cr8s::rocket_routes::rustaceans::create_rustacean,
                 cr8s::rocket_routes::rustaceans::update_rustacean,
+                cr8s::rocket_routes::rustaceans::patch_rustacean,
                 cr8s::rocket_routes::rustaceans::delete_rustacean,
+                cr8s::rocket_routes::notifications::get_notifications,
+                cr8s::rocket_routes::notifications::update_notifications,
+                cr8s::rocket_routes::notifications::confirm_email_verification,
+                cr8s::rocket_routes::notifications::verify_email,
+                cr8s::rocket_routes::subscriptions::get_subscriptions,
+                cr8s::rocket_routes::subscriptions::create_subscription,
+                cr8s::rocket_routes::subscriptions::delete_subscription,
+                cr8s::rocket_routes::subscriptions::unsubscribe,
+                cr8s::rocket_routes::subscriptions::unsubscribe_one_click,
+                cr8s::rocket_routes::webhooks::get_webhooks,
+                cr8s::rocket_routes::webhooks::view_webhook,
+                cr8s::rocket_routes::webhooks::create_webhook,
+                cr8s::rocket_routes::webhooks::update_webhook,
+                cr8s::rocket_routes::webhooks::delete_webhook,
+                cr8s::rocket_routes::webhooks::get_webhook_deliveries,
+                cr8s::rocket_routes::webhooks::redeliver_webhook,
+            ],
+        )
+        .register(
+            "/",
+            rocket::catchers![
+                cr8s::rocket_routes::errors::unauthorized,
+                cr8s::rocket_routes::errors::forbidden,
+                cr8s::rocket_routes::errors::not_found,
+                cr8s::rocket_routes::errors::unprocessable_entity,
+                cr8s::rocket_routes::errors::precondition_required,
+                cr8s::rocket_routes::errors::internal_error,
             ],
         )
-        .attach(cr8s::rocket_routes::Cors)
+        .manage(Mailer(Arc::new(mailer)))
+        .attach(cr8s::rocket_routes::access_log::AccessLog)
+        .attach(cr8s::rocket_routes::cors::Cors)
+        .attach(cr8s::rocket_routes::metrics::Metrics)
+        .attach(cr8s::migrations::Migrations)
         .attach(cr8s::rocket_routes::DbConnection::fairing())
         .attach(cr8s::rocket_routes::CacheConnection::init())
+        .attach(cr8s::scheduler::Scheduler)
         .launch()
         .await;
 }
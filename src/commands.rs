@@ -1,25 +1,63 @@
-use chrono::{Datelike, Utc};
-use diesel::{Connection, PgConnection};
-use lettre::transport::smtp::authentication::Credentials;
+use diesel::{Connection, OptionalExtension, PgConnection};
+use rocket_db_pools::deadpool_redis::redis;
+use std::error::Error;
 use std::str::FromStr;
-use tera::{Context, Tera};
 
 use crate::auth;
-use crate::mail::HtmlMailer;
-use crate::models::{NewUser, RoleCode};
-use crate::repositories::{CrateRepository, RoleRepository, UserRepository};
+use crate::config;
+use crate::digest;
+use crate::events::{self, Event};
+use crate::mail::{self, HtmlMailer};
+use crate::migrations;
+use crate::models::{Actor, DigestFrequency, NewRole, NewUser, OutboxStatus, RoleCode, User};
+use crate::outbox;
+use crate::repositories::{
+    AuditRepository, OutboxRepository, RoleRepository, SessionRepository, UserRepository,
+};
+use crate::seed::{self, SeedProfile};
+use crate::webhooks;
 
 pub fn load_db_connection() -> PgConnection {
-    let database_url = std::env::var("DATABASE_URL").expect("Cannot read DB url from env");
-    PgConnection::establish(&database_url).expect("Cannot connect to postgres")
+    let database_url = &config::get().databases.postgres.url;
+    PgConnection::establish(database_url).expect("Cannot connect to postgres")
 }
 
-fn load_template_engine() -> Tera {
-    Tera::new("templates/**/*.html").unwrap_or_else(|e| {
-        panic!("Parsing error(s): {}", e);
+/// The mailer handed to `events::publish`, whose handlers queue notifications.
+fn load_mailer() -> HtmlMailer {
+    HtmlMailer::from_config().unwrap_or_else(|e| {
+        panic!("Cannot load mailer: {}", e);
     })
 }
 
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// Connects to the redis holding the sessions. Commands changing users cannot do without
+/// it, the users would keep their access until their sessions expire.
+fn load_cache_connection() -> redis::Connection {
+    let Some(redis_config) = &config::get().databases.redis else {
+        exit_with_error("Redis is not configured (REDIS_URL), cannot revoke the sessions of users");
+    };
+    redis::Client::open(redis_config.url.as_str())
+        .and_then(|client| client.get_connection())
+        .unwrap_or_else(|e| exit_with_error(&format!("Cannot connect to redis: {}", e)))
+}
+
+/// Ends the sessions of users whose roles or credentials changed, they log in again.
+fn revoke_sessions(cache: &mut redis::Connection, user_ids: &[i32]) {
+    for user_id in user_ids {
+        SessionRepository::revoke_user_sessions(cache, *user_id).unwrap_or_else(|e| {
+            exit_with_error(&format!(
+                "Cannot revoke sessions of user {}: {}",
+                user_id, e
+            ))
+        });
+        println!("Sessions of user {} revoked", user_id);
+    }
+}
+
 pub fn create_user(username: String, password: String, role_codes: Vec<String>) {
     let mut connection = load_db_connection();
 
@@ -31,10 +69,23 @@ pub fn create_user(username: String, password: String, role_codes: Vec<String>)
 
     let role_codes = role_codes
         .iter()
-        .map(|v| RoleCode::from_str(&v).unwrap())
-        .collect();
+        .map(|v| RoleCode::from_str(v))
+        .collect::<Result<Vec<RoleCode>, String>>()
+        .unwrap_or_else(|e| exit_with_error(&e));
+    let mailer = load_mailer();
 
-    let user = UserRepository::create(&mut connection, new_user, role_codes).unwrap();
+    let user = connection
+        .transaction(|connection| {
+            let user = UserRepository::create(connection, new_user, role_codes)?;
+            events::publish(connection, &mailer, &Event::created(&Actor::cli(), &user))?;
+            Ok::<User, diesel::result::Error>(user)
+        })
+        .unwrap_or_else(|e| match e {
+            diesel::result::Error::NotFound => {
+                exit_with_error("A role is missing from the database, run `cli db migrate`")
+            }
+            e => exit_with_error(&format!("Cannot create user: {}", e)),
+        });
     println!("User created: {:?}", user);
     let roles = RoleRepository::find_by_user(&mut connection, &user).unwrap();
     for role in roles {
@@ -57,40 +108,257 @@ pub fn list_users() {
 
 pub fn delete_user(id: i32) {
     let mut connection = load_db_connection();
+    // Connected before the deletion, which must not happen without revoking the sessions
+    let mut cache = load_cache_connection();
+    let mailer = load_mailer();
 
-    UserRepository::delete(&mut connection, id).unwrap();
+    connection
+        .transaction(|connection| {
+            let user = UserRepository::find(connection, id)?;
+            UserRepository::delete(connection, id)?;
+            events::publish(connection, &mailer, &Event::deleted(&Actor::cli(), &user))
+        })
+        .unwrap();
+    revoke_sessions(&mut cache, &[id]);
+}
+
+pub fn audit_tail(limit: i64) {
+    let mut connection = load_db_connection();
+
+    let events = AuditRepository::find_multiple(&mut connection, None, None, None, limit).unwrap();
+    for event in events.iter().rev() {
+        println!(
+            "{} [{}] {} {} #{} by {}",
+            event.created_at,
+            event.request_id.as_deref().unwrap_or("cli"),
+            event.action,
+            event.entity,
+            event.entity_id,
+            event
+                .actor_id
+                .map(|id| format!("user #{}", id))
+                .unwrap_or_else(|| "cli".to_string()),
+        );
+    }
 }
 
 pub fn send_digest(to: Vec<String>, hours_since: i32, subject: Option<String>) {
     let mut connection = load_db_connection();
 
-    let crates = CrateRepository::find_since(&mut connection, hours_since).unwrap();
+    let digest = digest::Digest::load(&mut connection, hours_since, None, None).unwrap();
 
-    if crates.len() > 0 {
-        println!("Sending digest for {} crates", crates.len());
+    if !digest.is_empty() {
+        println!("Sending digest for {} crates", digest.len());
 
-        let mut context = Context::new();
-        context.insert("crates", &crates);
-        let year = Utc::now().year();
-        context.insert("year", &year);
+        let context =
+            digest.context(&digest::DateFormat::from_config(&config::get().digest).unwrap());
+        let mailer = load_mailer();
 
-        let smtp_host = std::env::var("SMTP_HOST").expect("Cannot load SMTP host from env");
-        let smtp_username =
-            std::env::var("SMTP_USERNAME").expect("Cannot load SMTP username from env");
-        let smtp_password =
-            std::env::var("SMTP_PASSWORD").expect("Cannot load SMTP password from env");
+        let messages = mailer
+            .render_messages(to, subject, digest::DIGEST_TEMPLATE, &context)
+            .unwrap();
+        connection
+            .transaction(|connection| {
+                messages
+                    .iter()
+                    .try_for_each(|message| outbox::enqueue(connection, message).map(|_| ()))
+            })
+            .unwrap();
+        deliver_outbox(&mut connection, &mailer);
+    }
+}
 
-        let credentials = Credentials::new(smtp_username, smtp_password);
-        let tera = load_template_engine();
+/// Renders the digest `send_digest` would send, without sending it.
+pub fn preview_digest(hours_since: i32, output: Option<String>) {
+    let mut connection = load_db_connection();
 
-        let mailer = HtmlMailer {
-            smtp_host,
-            credentials,
-            template_engine: tera,
-        };
+    let html = digest::render_digest(&mut connection, hours_since).unwrap();
+    match output {
+        Some(path) => {
+            std::fs::write(&path, html).unwrap();
+            println!("Digest written to {}", path);
+        }
+        None => println!("{}", html),
+    }
+}
 
-        mailer
-            .send(to, subject, "email/digest.html", &context)
-            .unwrap();
+/// Queues one digest per subscription, each with its own window, filters and unsubscribe link.
+pub fn send_subscriber_digests(frequency: Option<DigestFrequency>, subject: Option<String>) {
+    let mut connection = load_db_connection();
+
+    let mailer = load_mailer();
+
+    let queued = digest::queue_subscriber_digests(&mut connection, &mailer, frequency, subject)
+        .unwrap_or_else(|e| {
+            panic!("Cannot queue digests: {}", e);
+        });
+    println!("Queued {} digests", queued);
+    deliver_outbox(&mut connection, &mailer);
+}
+
+fn deliver_outbox(connection: &mut PgConnection, mailer: &HtmlMailer) {
+    let report = outbox::deliver_due(connection, mailer.transport.as_ref(), None).unwrap();
+    println!("Outbox delivery: {}", report);
+}
+
+pub fn outbox_deliver(to: Option<&str>) {
+    let mut connection = load_db_connection();
+
+    let transport = mail::load_transport().unwrap_or_else(|e| {
+        panic!("Cannot load mail transport: {}", e);
+    });
+    let report = outbox::deliver_due(&mut connection, transport.as_ref(), to).unwrap();
+    println!("Outbox delivery: {}", report);
+}
+
+pub fn webhooks_deliver() {
+    let mut connection = load_db_connection();
+
+    let report = webhooks::deliver_due(&mut connection).unwrap_or_else(|e| {
+        panic!("Cannot deliver webhooks: {}", e);
+    });
+    println!("Webhook delivery: {}", report);
+}
+
+pub fn outbox_list(status: Option<OutboxStatus>, limit: i64) {
+    let mut connection = load_db_connection();
+
+    let emails = OutboxRepository::find_multiple(&mut connection, status, limit).unwrap();
+    for email in emails.iter().rev() {
+        println!(
+            "#{} {} attempts={} next_attempt_at={} to={} subject={:?}",
+            email.id,
+            email.status,
+            email.attempts,
+            email.next_attempt_at,
+            email.recipients.join(","),
+            email.subject.as_deref().unwrap_or(""),
+        );
+        if let Some(last_error) = &email.last_error {
+            println!("\tlast error: {}", last_error);
+        }
+    }
+}
+
+/// Requeues a failed email by id, or every dead-lettered email without one.
+pub fn outbox_requeue(id: Option<i32>) {
+    let mut connection = load_db_connection();
+
+    let requeued = OutboxRepository::requeue(&mut connection, id).unwrap();
+    println!("Requeued {} emails", requeued);
+}
+
+pub fn db_migrate() {
+    let mut connection = load_db_connection();
+
+    let versions = migrations::run_pending(&mut connection).unwrap_or_else(|e| {
+        panic!("Cannot run migrations: {}", e);
+    });
+    for version in &versions {
+        println!("Applied migration {}", version);
+    }
+    println!("{} migrations applied", versions.len());
+}
+
+/// Ids of the users, none when the migration creating their table is not applied.
+fn find_user_ids(connection: &mut PgConnection) -> Vec<i32> {
+    UserRepository::find_ids(connection).unwrap_or_default()
+}
+
+pub fn db_rollback() {
+    let mut connection = load_db_connection();
+    // Reverting the migration of the users drops them, which must not happen without
+    // revoking their sessions, so connected before as in `delete_user`
+    let user_ids = find_user_ids(&mut connection);
+    let mut cache = (!user_ids.is_empty()).then(load_cache_connection);
+
+    match migrations::revert_last(&mut connection) {
+        Ok(Some(version)) => println!("Reverted migration {}", version),
+        Ok(None) => println!("No migration to revert"),
+        Err(e) => panic!("Cannot revert migration: {}", e),
+    }
+    if let Some(cache) = cache.as_mut() {
+        let remaining_ids = find_user_ids(&mut connection);
+        let removed_ids: Vec<i32> = user_ids
+            .into_iter()
+            .filter(|id| !remaining_ids.contains(id))
+            .collect();
+        revoke_sessions(cache, &removed_ids);
+    }
+}
+
+pub fn db_status() {
+    let mut connection = load_db_connection();
+
+    let statuses = migrations::status(&mut connection).unwrap_or_else(|e| {
+        panic!("Cannot read migrations: {}", e);
+    });
+    for status in statuses {
+        let state = if status.applied { "applied" } else { "pending" };
+        println!("{:<8} {}", state, status.name);
+    }
+}
+
+/// Reverts and reapplies every migration, dropping all data. The sessions go with the
+/// users, they would keep their roles and the next users reuse their ids.
+pub fn db_reset() {
+    let mut connection = load_db_connection();
+    let mut cache = load_cache_connection();
+
+    let versions = migrations::reset(&mut connection).unwrap_or_else(|e| {
+        panic!("Cannot reset database: {}", e);
+    });
+    println!("Database reset, {} migrations applied", versions.len());
+    SessionRepository::revoke_all_sessions(&mut cache)
+        .unwrap_or_else(|e| exit_with_error(&format!("Cannot revoke sessions: {}", e)));
+    println!("Sessions of every user revoked");
+}
+
+pub fn db_seed(profile: SeedProfile) {
+    let mut connection = load_db_connection();
+
+    let mailer = load_mailer();
+    // Connected before committing password and role resets, which must not happen
+    // without revoking the sessions of their users
+    let mut cache = None;
+    let report = connection
+        .transaction::<_, Box<dyn Error>, _>(|connection| {
+            let report = seed::seed(connection, &mailer, profile)?;
+            if !report.updated_users.is_empty() {
+                cache = Some(load_cache_connection());
+            }
+            Ok(report)
+        })
+        .unwrap_or_else(|e| {
+            panic!("Cannot seed database: {}", e);
+        });
+    println!("Seeded {} profile: {}", profile, report);
+    if let Some(cache) = cache.as_mut() {
+        revoke_sessions(cache, &report.updated_users);
+    }
+}
+
+pub fn roles_list() {
+    let mut connection = load_db_connection();
+
+    let roles = RoleRepository::find_all(&mut connection).unwrap();
+    for role in roles {
+        println!("{:<8} {}", role.code.to_string(), role.name);
+    }
+}
+
+pub fn roles_create(code: String, name: String) {
+    let mut connection = load_db_connection();
+
+    let code = RoleCode::from_str(&code).unwrap_or_else(|e| exit_with_error(&e));
+    if RoleRepository::find_by_code(&mut connection, &code)
+        .optional()
+        .unwrap()
+        .is_some()
+    {
+        exit_with_error(&format!("Role '{}' already exists", code));
     }
+    let role = RoleRepository::create(&mut connection, NewRole { code, name })
+        .unwrap_or_else(|e| exit_with_error(&format!("Cannot create role: {}", e)));
+    println!("Role created: {:?}", role);
 }
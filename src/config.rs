@@ -0,0 +1,370 @@
+use std::sync::OnceLock;
+
+use rocket::figment::providers::Env;
+use rocket::figment::Figment;
+use serde::{Deserialize, Deserializer};
+
+use crate::digest::DateFormat;
+use crate::mail::DeliveryMode;
+use crate::rocket_routes::cors;
+use crate::scheduler::Jobs;
+
+/// Environment variables predating the figment configuration, with the keys they set.
+const ENV_KEYS: [(&str, &str); 15] = [
+    ("DATABASE_URL", "databases.postgres.url"),
+    ("REDIS_URL", "databases.redis.url"),
+    ("APP_BASE_URL", "base_url"),
+    ("SIGNING_SECRET", "signing_secret"),
+    ("SCHEDULER_JOBS", "scheduler.jobs"),
+    ("MAIL_TRANSPORT", "mail.transport"),
+    ("MAIL_FROM", "mail.from"),
+    ("MAIL_REPLY_TO", "mail.reply_to"),
+    ("MAIL_DELIVERY", "mail.delivery"),
+    ("MAIL_FILE_DIR", "mail.file_dir"),
+    ("SMTP_HOST", "mail.smtp.host"),
+    ("SMTP_USERNAME", "mail.smtp.username"),
+    ("SMTP_PASSWORD", "mail.smtp.password"),
+    ("DIGEST_TIMEZONE", "digest.timezone"),
+    ("DIGEST_LOCALE", "digest.locale"),
+];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings of both binaries, read from `Rocket.toml`, `ROCKET_*` variables and the
+/// variables of `ENV_KEYS`, e.g. `SMTP_HOST` or `ROCKET_MAIL={smtp={host="..."}}`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub databases: DatabasesConfig,
+    /// Public URL of the API, used in links sent by email.
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// Key of the signed unsubscribe links.
+    #[serde(default, deserialize_with = "lossy_string")]
+    pub signing_secret: Option<String>,
+    /// Whether the server applies the pending migrations before serving requests.
+    #[serde(default)]
+    pub run_migrations_on_startup: bool,
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+    #[serde(default)]
+    pub mail: MailConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub page_limits: PageLimitsConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    #[serde(default)]
+    pub digest: DigestConfig,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DatabasesConfig {
+    pub postgres: DatabaseConfig,
+    /// Holds the sessions, the cli revokes them through it when users change.
+    pub redis: Option<DatabaseConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DatabaseConfig {
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionsConfig {
+    pub lifetime_seconds: usize,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        SessionsConfig {
+            lifetime_seconds: 3 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Smtp,
+    File,
+    Stdout,
+    Memory,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SmtpConfig {
+    pub host: Option<String>,
+    #[serde(default, deserialize_with = "lossy_string")]
+    pub username: Option<String>,
+    #[serde(default, deserialize_with = "lossy_string")]
+    pub password: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    pub transport: TransportKind,
+    pub from: String,
+    pub reply_to: Option<String>,
+    pub delivery: DeliveryMode,
+    /// Directory of the `file` transport.
+    pub file_dir: String,
+    pub smtp: SmtpConfig,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            transport: TransportKind::Smtp,
+            from: "Cr8s <info@cr8s.com>".to_string(),
+            reply_to: None,
+            delivery: DeliveryMode::Bcc,
+            file_dir: "mail".to_string(),
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+/// Origins are exact, e.g. `https://cr8s.com`, any subdomain, e.g. `https://*.cr8s.com`,
+/// or `*` for any origin; the allowed one is echoed back in the responses. `*` is only
+/// accepted without credentials and answered with a literal `*`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Response headers readable by the browser scripts.
+    pub exposed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    /// How long browsers cache a preflight.
+    pub max_age_seconds: u32,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        CorsConfig {
+            allowed_origins: strings(&["https://app.cr8s.com"]),
+            allowed_methods: strings(&["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]),
+            allowed_headers: strings(&[
+                "Authorization",
+                "Content-Type",
+                "If-Match",
+                "If-None-Match",
+                "X-Request-Id",
+            ]),
+            exposed_headers: strings(&["ETag", "X-Request-Id"]),
+            allow_credentials: true,
+            max_age_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Page sizes of the listings when the request sets no `limit`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PageLimitsConfig {
+    pub crates: i64,
+    pub rustaceans: i64,
+    pub audit_events: i64,
+    pub webhook_deliveries: i64,
+}
+
+impl Default for PageLimitsConfig {
+    fn default() -> Self {
+        PageLimitsConfig {
+            crates: 100,
+            rustaceans: 100,
+            audit_events: 100,
+            webhook_deliveries: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Directory of the Tera templates.
+    pub templates: String,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            templates: "templates".to_string(),
+        }
+    }
+}
+
+/// Timezone (e.g. `Europe/Berlin`) and locale (e.g. `de_DE`) of the digest dates.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DigestConfig {
+    pub timezone: String,
+    pub locale: String,
+    /// How long the unsubscribe links of a digest keep working.
+    pub unsubscribe_token_days: u32,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        DigestConfig {
+            timezone: "UTC".to_string(),
+            locale: "en_US".to_string(),
+            unsubscribe_token_days: 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    /// `;` separated `job=cron expression` entries, see `Jobs::parse`.
+    pub jobs: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    /// Whether webhooks may call loopback, link-local and private addresses, which
+    /// otherwise stay out of reach so webhooks cannot probe internal services.
+    pub allow_private_hosts: bool,
+}
+
+/// Reads secrets that environment variables turned into numbers or booleans as strings.
+fn lossy_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        Text(String),
+        Unsigned(u64),
+        Signed(i64),
+        Float(f64),
+        Bool(bool),
+    }
+
+    Ok(
+        Option::<Scalar>::deserialize(deserializer)?.map(|scalar| match scalar {
+            Scalar::Text(text) => text,
+            Scalar::Unsigned(number) => number.to_string(),
+            Scalar::Signed(number) => number.to_string(),
+            Scalar::Float(number) => number.to_string(),
+            Scalar::Bool(flag) => flag.to_string(),
+        }),
+    )
+}
+
+fn default_base_url() -> String {
+    "http://127.0.0.1:8000".to_string()
+}
+
+fn env_key(name: &str) -> Option<&'static str> {
+    ENV_KEYS
+        .iter()
+        .find(|(env, _)| name.eq_ignore_ascii_case(env))
+        .map(|(_, key)| *key)
+}
+
+/// Rocket's figment extended with the variables of `ENV_KEYS`, which take precedence.
+pub fn figment() -> Figment {
+    rocket::Config::figment().merge(
+        Env::raw()
+            .filter(|key| env_key(key.as_str()).is_some())
+            .map(|key| env_key(key.as_str()).unwrap_or_default().into())
+            .global(),
+    )
+}
+
+impl Config {
+    fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if !self.base_url.starts_with("http://") && !self.base_url.starts_with("https://") {
+            errors.push(format!(
+                "base_url: '{}' is not an http(s) URL",
+                self.base_url
+            ));
+        }
+        if self.signing_secret.as_deref() == Some("") {
+            errors.push("signing_secret: cannot be empty".to_string());
+        }
+        if self.sessions.lifetime_seconds == 0 {
+            errors.push("sessions.lifetime_seconds: must be positive".to_string());
+        }
+        if self.digest.unsubscribe_token_days == 0 {
+            errors.push("digest.unsubscribe_token_days: must be positive".to_string());
+        }
+        if let Err(e) = self.mail.from.parse::<lettre::message::Mailbox>() {
+            errors.push(format!("mail.from: {}", e));
+        }
+        if let Some(Err(e)) = self
+            .mail
+            .reply_to
+            .as_ref()
+            .map(|reply_to| reply_to.parse::<lettre::message::Mailbox>())
+        {
+            errors.push(format!("mail.reply_to: {}", e));
+        }
+        for origin in &self.cors.allowed_origins {
+            if let Err(e) = cors::check_origin_pattern(origin) {
+                errors.push(format!("cors.allowed_origins: {}", e));
+            }
+        }
+        if self.cors.allow_credentials && self.cors.allowed_origins.iter().any(|o| o == "*") {
+            errors.push(
+                "cors.allowed_origins: '*' cannot be combined with allow_credentials".to_string(),
+            );
+        }
+        for method in &self.cors.allowed_methods {
+            if let Err(e) = cors::check_method(method) {
+                errors.push(format!("cors.allowed_methods: {}", e));
+            }
+        }
+        let page_limits = &self.page_limits;
+        let limits = [
+            page_limits.crates,
+            page_limits.rustaceans,
+            page_limits.audit_events,
+            page_limits.webhook_deliveries,
+        ];
+        if limits.iter().any(|limit| *limit <= 0) {
+            errors.push("page_limits: must be positive".to_string());
+        }
+        if let Err(e) = DateFormat::from_config(&self.digest) {
+            errors.push(format!("digest: {}", e));
+        }
+        if let Err(e) = Jobs::parse(&self.scheduler.jobs) {
+            errors.push(format!("scheduler.jobs: {}", e));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Extracts and validates the configuration, errors list every invalid setting.
+    pub fn load(figment: &Figment) -> Result<Config, String> {
+        let config: Config = figment.extract().map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Loads the configuration of the process, once at startup.
+pub fn init(figment: &Figment) -> Result<&'static Config, String> {
+    let config = Config::load(figment)?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// The configuration loaded by `init`.
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("configuration is loaded at startup")
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+
+use chrono::{Datelike, Locale, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use diesel::{Connection, PgConnection, QueryResult};
+use serde::Serialize;
+use tera::Context;
+
+use crate::auth;
+use crate::config::{self, DigestConfig};
+use crate::mail::{self, HtmlMailer, ListUnsubscribe, ListUnsubscribePost};
+use crate::models::{Crate, DigestFrequency, Rustacean};
+use crate::outbox;
+use crate::repositories::{AuditRepository, CrateRepository, DigestSubscriptionRepository};
+
+const DATE_FORMAT: &str = "%e %B %Y, %H:%M %Z";
+
+pub const DIGEST_TEMPLATE: &str = "email/digest.html";
+
+/// Timezone and locale the digest dates are formatted in.
+pub struct DateFormat {
+    pub timezone: Tz,
+    pub locale: Locale,
+}
+
+impl DateFormat {
+    pub fn from_config(digest: &DigestConfig) -> Result<Self, String> {
+        let timezone = digest.timezone.parse::<Tz>()?;
+        let locale = Locale::try_from(digest.locale.as_str())
+            .map_err(|_| format!("Unknown digest locale '{}'", digest.locale))?;
+        Ok(DateFormat { timezone, locale })
+    }
+
+    fn format(&self, date: NaiveDateTime) -> String {
+        date.and_utc()
+            .with_timezone(&self.timezone)
+            .format_localized(DATE_FORMAT, self.locale)
+            .to_string()
+    }
+}
+
+/// A crate of the digest, either created or released with a new version in the window.
+pub struct DigestEntry {
+    pub a_crate: Crate,
+    pub author: Rustacean,
+    pub is_new: bool,
+}
+
+#[derive(Serialize)]
+struct DigestCrate<'a> {
+    #[serde(flatten)]
+    a_crate: &'a Crate,
+    is_new: bool,
+    created: String,
+    updated: String,
+}
+
+impl<'a> DigestCrate<'a> {
+    fn new(entry: &'a DigestEntry, date_format: &DateFormat) -> Self {
+        DigestCrate {
+            a_crate: &entry.a_crate,
+            is_new: entry.is_new,
+            created: date_format.format(entry.a_crate.created_at),
+            updated: date_format.format(entry.a_crate.updated_at),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DigestAuthor<'a> {
+    author: &'a Rustacean,
+    crates: Vec<DigestCrate<'a>>,
+}
+
+pub struct Digest {
+    pub hours_since: i32,
+    pub entries: Vec<DigestEntry>,
+}
+
+impl Digest {
+    /// Loads the crates created or with a new version in the last `hours_since` hours, grouped
+    /// by author, narrowed to a rustacean and/or a keyword.
+    pub fn load(
+        connection: &mut PgConnection,
+        hours_since: i32,
+        rustacean_id: Option<i32>,
+        keyword: Option<&str>,
+    ) -> QueryResult<Self> {
+        let updated_ids = AuditRepository::find_crate_version_changes(connection, hours_since)?;
+        let entries = CrateRepository::find_changed_since(
+            connection,
+            hours_since,
+            &updated_ids,
+            rustacean_id,
+            keyword,
+        )?
+        .into_iter()
+        .map(|(a_crate, author, is_new)| DigestEntry {
+            a_crate,
+            author,
+            is_new,
+        })
+        .collect();
+        Ok(Digest {
+            hours_since,
+            entries,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Template context with the crates both flat in `crates` and grouped by author in `authors`.
+    pub fn context(&self, date_format: &DateFormat) -> Context {
+        let digest_crate = |entry| DigestCrate::new(entry, date_format);
+        let mut authors: Vec<DigestAuthor> = Vec::new();
+        for entry in self.entries.iter() {
+            match authors.last_mut() {
+                Some(last) if last.author.id == entry.author.id => {
+                    last.crates.push(digest_crate(entry))
+                }
+                _ => authors.push(DigestAuthor {
+                    author: &entry.author,
+                    crates: vec![digest_crate(entry)],
+                }),
+            }
+        }
+        let crates: Vec<DigestCrate> = self.entries.iter().map(digest_crate).collect();
+
+        let mut context = Context::new();
+        context.insert("crates", &crates);
+        context.insert("authors", &authors);
+        context.insert(
+            "new_count",
+            &self.entries.iter().filter(|entry| entry.is_new).count(),
+        );
+        context.insert(
+            "updated_count",
+            &self.entries.iter().filter(|entry| !entry.is_new).count(),
+        );
+        context.insert("hours_since", &self.hours_since);
+        let year = Utc::now().year();
+        context.insert("year", &year);
+        context
+    }
+}
+
+/// Renders the HTML digest of the crates changed in the last `hours_since` hours.
+pub fn render_digest(
+    connection: &mut PgConnection,
+    hours_since: i32,
+) -> Result<String, Box<dyn Error>> {
+    let digest = Digest::load(connection, hours_since, None, None)?;
+    let template_engine = mail::load_template_engine()?;
+    Ok(template_engine.render(
+        DIGEST_TEMPLATE,
+        &digest.context(&DateFormat::from_config(&config::get().digest)?),
+    )?)
+}
+
+/// Queues one digest per subscription in the outbox, each with its own window, filters and
+/// unsubscribe link. Subscriptions without new matching crates are skipped, returns the number
+/// of digests queued.
+pub fn queue_subscriber_digests(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    frequency: Option<DigestFrequency>,
+    subject: Option<String>,
+) -> Result<usize, Box<dyn Error>> {
+    let base_url = &config::get().base_url;
+    let date_format = DateFormat::from_config(&config::get().digest)?;
+
+    let mut queued = 0;
+    let subscriptions = DigestSubscriptionRepository::find_multiple(connection, frequency)?;
+    for subscription in subscriptions {
+        let digest = Digest::load(
+            connection,
+            subscription.frequency.hours(),
+            subscription.rustacean_id,
+            subscription.keyword.as_deref(),
+        )?;
+        if digest.is_empty() {
+            continue;
+        }
+        log::info!(
+            "Queueing {} digest for {} crates to subscription #{}",
+            subscription.frequency,
+            digest.len(),
+            subscription.id
+        );
+
+        let mut context = digest.context(&date_format);
+        let token = auth::sign_unsubscribe_token(subscription.id)?;
+        let unsubscribe_url = format!(
+            "{}/unsubscribe?token={}",
+            base_url.trim_end_matches('/'),
+            token
+        );
+        context.insert("unsubscribe_url", &unsubscribe_url);
+
+        let messages = mailer.render_messages_with(
+            vec![subscription.email.clone()],
+            subject.clone(),
+            DIGEST_TEMPLATE,
+            &context,
+            |builder| {
+                builder
+                    .header(ListUnsubscribe(unsubscribe_url.clone()))
+                    .header(ListUnsubscribePost)
+            },
+        )?;
+        connection.transaction(|connection| {
+            for message in messages.iter() {
+                outbox::enqueue(connection, message)?;
+            }
+            DigestSubscriptionRepository::mark_sent(connection, subscription.id)
+        })?;
+        queued += 1;
+    }
+    Ok(queued)
+}
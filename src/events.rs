@@ -0,0 +1,140 @@
+use diesel::{PgConnection, QueryResult};
+
+use crate::mail::HtmlMailer;
+use crate::models::{Actor, Auditable, Crate, Rustacean, User};
+use crate::notifications;
+use crate::repositories::AuditRepository;
+use crate::webhooks;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// Names of the events sent to webhooks.
+pub const EVENT_NAMES: [&str; 8] = [
+    "crate.created",
+    "crate.updated",
+    "crate.deleted",
+    "rustacean.created",
+    "rustacean.updated",
+    "rustacean.deleted",
+    "user.created",
+    "user.deleted",
+];
+
+impl EventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Updated => "updated",
+            EventKind::Deleted => "deleted",
+        }
+    }
+
+    /// Action under which the event is recorded in the audit log.
+    pub fn action(&self) -> &'static str {
+        match self {
+            EventKind::Created => "create",
+            EventKind::Updated => "update",
+            EventKind::Deleted => "delete",
+        }
+    }
+}
+
+/// A mutation of an entity, `before` is empty on create and `after` on delete.
+pub struct Event<'a, T> {
+    pub kind: EventKind,
+    pub actor: &'a Actor,
+    pub before: Option<&'a T>,
+    pub after: Option<&'a T>,
+}
+
+impl<'a, T> Event<'a, T> {
+    pub fn created(actor: &'a Actor, after: &'a T) -> Self {
+        Event {
+            kind: EventKind::Created,
+            actor,
+            before: None,
+            after: Some(after),
+        }
+    }
+
+    pub fn updated(actor: &'a Actor, before: &'a T, after: &'a T) -> Self {
+        Event {
+            kind: EventKind::Updated,
+            actor,
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    pub fn deleted(actor: &'a Actor, before: &'a T) -> Self {
+        Event {
+            kind: EventKind::Deleted,
+            actor,
+            before: Some(before),
+            after: None,
+        }
+    }
+
+    /// The entity as it is after the event, or was before its deletion.
+    pub fn entity(&self) -> &'a T {
+        self.after
+            .or(self.before)
+            .expect("an event has a state before or after")
+    }
+}
+
+impl<'a, T: Auditable> Event<'a, T> {
+    /// Name of the event such as `crate.updated`.
+    pub fn name(&self) -> String {
+        format!("{}.{}", T::ENTITY, self.kind.name())
+    }
+}
+
+/// Entities whose mutations are published as domain events.
+pub trait Publishable: Auditable + Sized {
+    /// Handles an event within the transaction of the mutation.
+    fn handle(
+        _connection: &mut PgConnection,
+        _mailer: &HtmlMailer,
+        _event: &Event<Self>,
+    ) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl Publishable for Rustacean {}
+
+impl Publishable for User {}
+
+impl Publishable for Crate {
+    fn handle(
+        connection: &mut PgConnection,
+        mailer: &HtmlMailer,
+        event: &Event<Self>,
+    ) -> QueryResult<()> {
+        notifications::notify_crate_owner(connection, mailer, event)
+    }
+}
+
+/// Records the event in the audit log, hands it to the handlers of the entity
+/// and queues its delivery to the webhooks listening to it.
+pub fn publish<T: Publishable>(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    event: &Event<T>,
+) -> QueryResult<()> {
+    AuditRepository::record(
+        connection,
+        event.actor,
+        event.kind.action(),
+        event.before,
+        event.after,
+    )?;
+    T::handle(connection, mailer, event)?;
+    webhooks::enqueue(connection, event)
+}
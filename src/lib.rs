@@ -1,8 +1,18 @@
 mod auth;
-mod mail;
+mod digest;
+mod events;
+mod metrics;
 mod models;
+mod notifications;
+mod outbox;
 mod repositories;
 mod schema;
+mod seed;
+mod webhooks;
 
 pub mod commands;
+pub mod config;
+pub mod mail;
+pub mod migrations;
 pub mod rocket_routes;
+pub mod scheduler;
@@ -1,43 +1,311 @@
-use lettre::message::header::ContentType;
-use lettre::transport::smtp::{authentication::Credentials, response::Response};
-use lettre::{SmtpTransport, Transport};
-use tera::Context;
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use lettre::address::Envelope;
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MessageBuilder, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::stub::StubTransport;
+use lettre::{FileTransport, Message, SmtpTransport, Transport};
+use serde::Deserialize;
+use tera::{Context, Tera};
+
+use crate::config::{self, TransportKind};
+
+const TEXT_WIDTH: usize = 80;
+
+/// Loads every template under the templates path, `.txt` companions included.
+pub fn load_template_engine() -> tera::Result<Tera> {
+    Tera::new(&format!("{}/**/*", config::get().paths.templates))
+}
+
+/// Object safe wrapper over the lettre transports, so the backend can be chosen at runtime.
+pub trait MailTransport: Send + Sync {
+    /// Delivers an already formatted message, e.g. one stored in the outbox.
+    fn deliver_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+impl<T> MailTransport for T
+where
+    T: Transport + Send + Sync,
+    T::Error: Error + 'static,
+{
+    fn deliver_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.send_raw(envelope, email)
+            .map(|_| ())
+            .map_err(|e| e.into())
+    }
+}
+
+/// Whether retrying a failed delivery is pointless, e.g. an SMTP 5xx reply.
+pub fn is_permanent_failure(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<lettre::transport::smtp::Error>()
+        .is_some_and(|e| e.is_permanent())
+}
+
+/// Prints every message to stdout instead of delivering it.
+pub struct StdoutTransport;
+
+impl Transport for StdoutTransport {
+    type Ok = ();
+    type Error = std::io::Error;
+
+    fn send_raw(&self, envelope: &Envelope, email: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(
+            stdout,
+            "From: {:?}\nTo: {:?}\n",
+            envelope.from(),
+            envelope.to()
+        )?;
+        stdout.write_all(email)?;
+        writeln!(stdout)
+    }
+}
+
+/// Keeps every message in memory, clones share the same message log.
+pub type InMemoryTransport = StubTransport;
+
+static IN_MEMORY_TRANSPORT: OnceLock<InMemoryTransport> = OnceLock::new();
+
+/// The transport of `mail.transport = "memory"`, shared by every mailer of the process.
+fn in_memory_transport() -> &'static InMemoryTransport {
+    IN_MEMORY_TRANSPORT.get_or_init(InMemoryTransport::new_ok)
+}
+
+/// Messages delivered so far by the in-memory transport, as (envelope, formatted message).
+pub fn sent_messages() -> Vec<(Envelope, String)> {
+    in_memory_transport().messages()
+}
+
+/// Builds the transport selected by `mail.transport` (`smtp`, `file`, `stdout` or `memory`).
+pub fn load_transport() -> Result<Box<dyn MailTransport>, Box<dyn Error>> {
+    let mail = &config::get().mail;
+    match mail.transport {
+        TransportKind::Smtp => {
+            let smtp = &mail.smtp;
+            let smtp_host = smtp
+                .host
+                .as_ref()
+                .ok_or("Missing mail.smtp.host (SMTP_HOST)")?;
+            let smtp_username = smtp
+                .username
+                .clone()
+                .ok_or("Missing mail.smtp.username (SMTP_USERNAME)")?;
+            let smtp_password = smtp
+                .password
+                .clone()
+                .ok_or("Missing mail.smtp.password (SMTP_PASSWORD)")?;
+
+            let credentials = Credentials::new(smtp_username, smtp_password);
+            let transport = SmtpTransport::relay(smtp_host)?
+                .credentials(credentials)
+                .build();
+            Ok(Box::new(transport))
+        }
+        TransportKind::File => {
+            let dir = PathBuf::from(&mail.file_dir);
+            std::fs::create_dir_all(&dir)?;
+            Ok(Box::new(FileTransport::with_envelope(dir)))
+        }
+        TransportKind::Stdout => Ok(Box::new(StdoutTransport)),
+        TransportKind::Memory => Ok(Box::new(in_memory_transport().clone())),
+    }
+}
+
+/// `List-Unsubscribe` header (RFC 2369) pointing mail clients to an unsubscribe URL.
+#[derive(Clone, Debug)]
+pub struct ListUnsubscribe(pub String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let url = s
+            .trim()
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix('>'))
+            .ok_or("List-Unsubscribe must be an <url>")?;
+        Ok(ListUnsubscribe(url.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+/// `List-Unsubscribe-Post: List-Unsubscribe=One-Click` (RFC 8058), telling mail clients
+/// that a POST to the `List-Unsubscribe` URL unsubscribes without further confirmation.
+#[derive(Clone, Debug)]
+pub struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match s.trim() {
+            "List-Unsubscribe=One-Click" => Ok(ListUnsubscribePost),
+            _ => Err("List-Unsubscribe-Post must be List-Unsubscribe=One-Click".into()),
+        }
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+/// How a message addressed to several recipients is delivered.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeliveryMode {
+    /// A single message with every recipient hidden in `Bcc`.
+    Bcc,
+    /// One message per recipient, rendered with `recipient` in the context.
+    PerRecipient,
+}
 
 pub struct HtmlMailer {
-    pub credentials: Credentials,
-    pub smtp_host: String,
     pub template_engine: tera::Tera,
+    pub transport: Box<dyn MailTransport>,
+    pub from: Mailbox,
+    pub reply_to: Option<Mailbox>,
+    pub delivery: DeliveryMode,
 }
 
 impl HtmlMailer {
-    pub fn send(
-        self,
-        to: Vec<String>,
-        subject: Option<String>,
+    /// Builds a mailer from the `mail` settings.
+    pub fn from_config() -> Result<Self, Box<dyn Error>> {
+        let mail = &config::get().mail;
+        let from = mail.from.parse()?;
+        let reply_to = match &mail.reply_to {
+            Some(reply_to) => Some(reply_to.parse()?),
+            None => None,
+        };
+        let delivery = mail.delivery;
+
+        Ok(HtmlMailer {
+            template_engine: load_template_engine()?,
+            transport: load_transport()?,
+            from,
+            reply_to,
+            delivery,
+        })
+    }
+
+    /// Renders the `.txt` companion of an HTML template, e.g. `email/digest.txt`
+    /// for `email/digest.html`, or converts the HTML when there is none.
+    fn render_text(
+        &self,
         template_name: &str,
         context: &Context,
-    ) -> Result<Response, Box<dyn std::error::Error>> {
-        let html_body = self.template_engine.render(template_name, &context)?;
-        let subject = subject.unwrap_or_else(|| "Cr8s digest".to_string());
+        html_body: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let text_template_name = match template_name.strip_suffix(".html") {
+            Some(stem) => format!("{}.txt", stem),
+            None => format!("{}.txt", template_name),
+        };
+        let has_text_template = self
+            .template_engine
+            .get_template_names()
+            .any(|name| name == text_template_name);
 
-        let mut message_builder = lettre::Message::builder()
-            .subject(subject)
-            .from("Ce8s <info@cr8s.com>".parse().unwrap())
-            .to(to[0].parse()?)
-            .header(ContentType::TEXT_HTML);
+        if has_text_template {
+            Ok(self.template_engine.render(&text_template_name, context)?)
+        } else {
+            Ok(html2text::from_read(html_body.as_bytes(), TEXT_WIDTH)?)
+        }
+    }
 
-        if to.len() > 1 {
-            for copy in &to[1..] {
-                message_builder = message_builder.cc(copy.parse()?);
-            }
+    fn render(
+        &self,
+        message_builder: MessageBuilder,
+        template_name: &str,
+        context: &Context,
+    ) -> Result<Message, Box<dyn Error>> {
+        let html_body = self.template_engine.render(template_name, context)?;
+        let text_body = self.render_text(template_name, context, &html_body)?;
+
+        let message =
+            message_builder.multipart(MultiPart::alternative_plain_html(text_body, html_body))?;
+        Ok(message)
+    }
+
+    fn message_builder(&self, subject: &str) -> MessageBuilder {
+        let message_builder = Message::builder().subject(subject).from(self.from.clone());
+        match &self.reply_to {
+            Some(reply_to) => message_builder.reply_to(reply_to.clone()),
+            None => message_builder,
         }
+    }
 
-        let message = message_builder.body(html_body)?;
+    /// Renders the messages `send` would deliver, one per recipient in per-recipient mode.
+    pub fn render_messages(
+        &self,
+        to: Vec<String>,
+        subject: Option<String>,
+        template_name: &str,
+        context: &Context,
+    ) -> Result<Vec<Message>, Box<dyn Error>> {
+        self.render_messages_with(to, subject, template_name, context, |builder| builder)
+    }
 
-        let mailer = SmtpTransport::relay(&self.smtp_host)?
-            .credentials(self.credentials)
-            .build();
+    /// Like `render_messages`, passing every message through `customize` first, e.g. to
+    /// add headers.
+    pub fn render_messages_with<F>(
+        &self,
+        to: Vec<String>,
+        subject: Option<String>,
+        template_name: &str,
+        context: &Context,
+        customize: F,
+    ) -> Result<Vec<Message>, Box<dyn Error>>
+    where
+        F: Fn(MessageBuilder) -> MessageBuilder,
+    {
+        if to.is_empty() {
+            return Err("Cannot send an email without recipients".into());
+        }
+        let subject = subject.unwrap_or_else(|| "Cr8s digest".to_string());
+        let recipients = to
+            .iter()
+            .map(|address| address.parse::<Mailbox>())
+            .collect::<Result<Vec<_>, _>>()?;
 
-        mailer.send(&message).map_err(|e| e.into())
+        match self.delivery {
+            DeliveryMode::Bcc => {
+                let message_builder = recipients.into_iter().fold(
+                    customize(self.message_builder(&subject)),
+                    |builder, recipient| builder.bcc(recipient),
+                );
+                Ok(vec![self.render(
+                    message_builder,
+                    template_name,
+                    context,
+                )?])
+            }
+            DeliveryMode::PerRecipient => recipients
+                .into_iter()
+                .map(|recipient| {
+                    let mut context = context.clone();
+                    context.insert("recipient", &recipient.email.to_string());
+                    context.insert(
+                        "recipient_name",
+                        &recipient
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| recipient.email.to_string()),
+                    );
+                    let message_builder = customize(self.message_builder(&subject)).to(recipient);
+                    self.render(message_builder, template_name, &context)
+                })
+                .collect(),
+        }
     }
 }
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::models::ConnectionCounts;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Label of the requests no route matched, which keeps arbitrary paths out of the labels.
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, made cumulative when rendered.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Method and route template of a request, e.g. `GET` and `/crates/<id>`.
+type RouteKey = (String, String);
+
+struct Metrics {
+    requests: BTreeMap<(RouteKey, &'static str), u64>,
+    latencies: BTreeMap<RouteKey, Histogram>,
+    logins: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            requests: BTreeMap::new(),
+            latencies: BTreeMap::new(),
+            logins: BTreeMap::new(),
+        }
+    }
+}
+
+/// Size of a pool, with its connections at scrape time when the pool exposes them.
+pub struct PoolGauges {
+    pub pool: &'static str,
+    pub connections: Option<ConnectionCounts>,
+    pub max: i64,
+}
+
+fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn metrics() -> std::sync::MutexGuard<'static, Metrics> {
+    // Counters stay usable even if a thread panicked while holding the lock
+    METRICS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn record_request(method: &str, route: &str, status: u16, latency: Duration) {
+    let key = (method.to_string(), route.to_string());
+    let mut metrics = metrics();
+    *metrics
+        .requests
+        .entry((key.clone(), status_class(status)))
+        .or_default() += 1;
+    metrics
+        .latencies
+        .entry(key)
+        .or_default()
+        .observe(latency.as_secs_f64());
+}
+
+pub fn record_login(success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    *metrics().logins.entry(outcome).or_default() += 1;
+}
+
+/// Renders every metric in the Prometheus text exposition format, the database
+/// connections only when the database answered.
+pub fn render(pools: &[PoolGauges], database: Option<&ConnectionCounts>) -> String {
+    let metrics = metrics();
+    let mut out = String::new();
+
+    out.push_str("# HELP http_requests_total Requests handled, by route and status class.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for (((method, route), status), count) in &metrics.requests {
+        let _ = writeln!(
+            out,
+            "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+            method,
+            escape(route),
+            status,
+            count
+        );
+    }
+
+    out.push_str("# HELP http_request_duration_seconds Latency of the requests, by route.\n");
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    for ((method, route), histogram) in &metrics.latencies {
+        let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            labels, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{{{}}} {}",
+            labels, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{{{}}} {}",
+            labels, histogram.count
+        );
+    }
+
+    out.push_str("# HELP logins_total Login attempts, by outcome.\n");
+    out.push_str("# TYPE logins_total counter\n");
+    for outcome in ["success", "failure"] {
+        let count = metrics.logins.get(outcome).copied().unwrap_or_default();
+        let _ = writeln!(out, "logins_total{{outcome=\"{}\"}} {}", outcome, count);
+    }
+
+    if let Some(database) = database {
+        out.push_str(
+            "# HELP database_connections Connections to the database from every client, \
+             by state (pg_stat_activity).\n",
+        );
+        out.push_str("# TYPE database_connections gauge\n");
+        for (state, value) in [("active", database.active), ("idle", database.idle)] {
+            let _ = writeln!(out, "database_connections{{state=\"{}\"}} {}", state, value);
+        }
+    }
+
+    out.push_str("# HELP pool_connections Connections of the pools, by state.\n");
+    out.push_str("# TYPE pool_connections gauge\n");
+    for (pool, connections) in pools
+        .iter()
+        .filter_map(|gauges| Some((gauges.pool, gauges.connections.as_ref()?)))
+    {
+        for (state, value) in [("active", connections.active), ("idle", connections.idle)] {
+            let _ = writeln!(
+                out,
+                "pool_connections{{pool=\"{}\",state=\"{}\"}} {}",
+                pool, state, value
+            );
+        }
+    }
+    out.push_str("# HELP pool_max_connections Maximum size of the pools.\n");
+    out.push_str("# TYPE pool_max_connections gauge\n");
+    for gauges in pools {
+        let _ = writeln!(
+            out,
+            "pool_max_connections{{pool=\"{}\"}} {}",
+            gauges.pool, gauges.max
+        );
+    }
+    out
+}
@@ -0,0 +1,117 @@
+use std::error::Error;
+
+use diesel::migration::{Migration, MigrationSource, MigrationVersion};
+use diesel::pg::Pg;
+use diesel::{Connection, PgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Rocket};
+
+use crate::config;
+use crate::repositories::DatabaseRepository;
+
+/// The `migrations/` directory, built into the binaries.
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+pub type MigrationResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A migration of `migrations/` and whether the database has applied it.
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+/// Runs `f` holding an advisory lock, so instances migrating or reverting at the same
+/// time wait for each other rather than interleave.
+fn with_lock<T>(
+    connection: &mut PgConnection,
+    f: impl FnOnce(&mut PgConnection) -> MigrationResult<T>,
+) -> MigrationResult<T> {
+    DatabaseRepository::lock_migrations(connection)?;
+    let result = f(connection);
+    DatabaseRepository::unlock_migrations(connection)?;
+    result
+}
+
+fn apply_pending(connection: &mut PgConnection) -> MigrationResult<Vec<String>> {
+    Ok(connection
+        .run_pending_migrations(MIGRATIONS)?
+        .iter()
+        .map(MigrationVersion::to_string)
+        .collect())
+}
+
+/// Applies the pending migrations, returning their names. Another instance migrating at
+/// the same time waits then finds nothing pending.
+pub fn run_pending(connection: &mut PgConnection) -> MigrationResult<Vec<String>> {
+    with_lock(connection, apply_pending)
+}
+
+/// Reverts the most recent applied migration, if any.
+pub fn revert_last(connection: &mut PgConnection) -> MigrationResult<Option<String>> {
+    with_lock(connection, |connection| {
+        if connection.applied_migrations()?.is_empty() {
+            return Ok(None);
+        }
+        let version = connection.revert_last_migration(MIGRATIONS)?;
+        Ok(Some(version.to_string()))
+    })
+}
+
+/// Reverts every applied migration then applies them all again, dropping every row.
+pub fn reset(connection: &mut PgConnection) -> MigrationResult<Vec<String>> {
+    with_lock(connection, |connection| {
+        connection.revert_all_migrations(MIGRATIONS)?;
+        apply_pending(connection)
+    })
+}
+
+pub fn status(connection: &mut PgConnection) -> MigrationResult<Vec<MigrationStatus>> {
+    let applied = connection.applied_migrations()?;
+    let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?;
+    Ok(migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+            name: migration.name().to_string(),
+            applied: applied.contains(&migration.name().version()),
+        })
+        .collect())
+}
+
+/// Applies the pending migrations before the routes go live when
+/// `run_migrations_on_startup` is set.
+pub struct Migrations;
+
+#[rocket::async_trait]
+impl Fairing for Migrations {
+    fn info(&self) -> Info {
+        Info {
+            name: "Run pending database migrations",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        if !config::get().run_migrations_on_startup {
+            return Ok(rocket);
+        }
+        let result = rocket::tokio::task::spawn_blocking(|| {
+            let mut connection = PgConnection::establish(&config::get().databases.postgres.url)?;
+            run_pending(&mut connection)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+        match result {
+            Ok(versions) => {
+                for version in versions {
+                    log::info!("Applied migration {}", version);
+                }
+                Ok(rocket)
+            }
+            Err(e) => {
+                log::error!("Cannot run migrations: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+}
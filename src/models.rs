@@ -1,6 +1,10 @@
 use std::{io::Write, str::FromStr};
 
-use crate::schema::{crates, roles, rustaceans, user_roles, users};
+use crate::schema::{
+    audit_events, crates, digest_subscriptions, email_outbox, notification_preferences,
+    role_permissions, roles, rustaceans, scheduled_jobs, user_roles, users, webhook_deliveries,
+    webhooks,
+};
 use chrono::NaiveDateTime;
 use diesel::{
     deserialize::{FromSql, FromSqlRow},
@@ -11,7 +15,7 @@ use diesel::{
     sql_types::Text,
     AsChangeset, Insertable, Queryable,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Queryable, AsChangeset, Deserialize, Serialize)]
 pub struct Rustacean {
@@ -21,6 +25,8 @@ pub struct Rustacean {
     pub email: String,
     #[serde(skip_deserializing)]
     pub created_at: NaiveDateTime,
+    #[serde(skip_deserializing)]
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -30,6 +36,20 @@ pub struct NewRustacean {
     pub email: String,
 }
 
+/// JSON Merge Patch for a rustacean, absent fields are left untouched.
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name=rustaceans)]
+pub struct RustaceanPatch {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl RustaceanPatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.email.is_none()
+    }
+}
+
 #[derive(Queryable, AsChangeset, Deserialize, Serialize)]
 pub struct Crate {
     #[serde(skip_deserializing)]
@@ -41,6 +61,8 @@ pub struct Crate {
     pub description: Option<String>,
     #[serde(skip_deserializing)]
     pub created_at: NaiveDateTime,
+    #[serde(skip_deserializing)]
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -53,6 +75,38 @@ pub struct NewCrate {
     pub description: Option<String>,
 }
 
+/// JSON Merge Patch for a crate, absent fields are left untouched
+/// and an explicit `null` description clears it.
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name=crates)]
+pub struct CratePatch {
+    pub rustacean_id: Option<i32>,
+    pub code: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub description: Option<Option<String>>,
+}
+
+impl CratePatch {
+    pub fn is_empty(&self) -> bool {
+        self.rustacean_id.is_none()
+            && self.code.is_none()
+            && self.name.is_none()
+            && self.version.is_none()
+            && self.description.is_none()
+    }
+}
+
+/// Distinguishes an explicit `null` (`Some(None)`) from an absent field (`None`).
+fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
 #[derive(Queryable, Debug, Identifiable, Serialize)]
 pub struct User {
     pub id: i32,
@@ -62,6 +116,14 @@ pub struct User {
     pub created_at: NaiveDateTime,
 }
 
+/// The logged in user as recorded in their session, resolved without querying Postgres.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SessionUser {
+    pub id: i32,
+    pub username: String,
+    pub roles: Vec<RoleCode>,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name=users)]
 pub struct NewUser {
@@ -69,7 +131,7 @@ pub struct NewUser {
     pub password: String,
 }
 
-#[derive(Queryable, Debug)]
+#[derive(Queryable, Debug, Serialize)]
 pub struct Role {
     pub id: i32,
     pub code: RoleCode,
@@ -84,6 +146,30 @@ pub struct NewRole {
     pub name: String,
 }
 
+#[derive(Queryable, Debug, Serialize)]
+pub struct Permission {
+    pub id: i32,
+    pub code: String,
+    pub description: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=role_permissions)]
+pub struct NewRolePermission {
+    pub role_id: i32,
+    pub permission_id: i32,
+}
+
+/// The permission codes granted to a role, as recorded in the audit log.
+#[derive(Serialize)]
+pub struct RolePermissions {
+    #[serde(skip_serializing)]
+    pub role_id: i32,
+    pub role: RoleCode,
+    pub permissions: Vec<String>,
+}
+
 #[derive(Queryable, Associations, Identifiable, Debug)]
 #[diesel(belongs_to(User))]
 #[diesel(belongs_to(Role))]
@@ -100,45 +186,389 @@ pub struct NewUserRole {
     pub role_id: i32,
 }
 
-#[derive(AsExpression, FromSqlRow, Debug)]
+#[derive(Queryable, Associations, Identifiable, Debug, Serialize)]
+#[diesel(belongs_to(User))]
+pub struct DigestSubscription {
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub user_id: i32,
+    pub email: String,
+    pub frequency: DigestFrequency,
+    pub rustacean_id: Option<i32>,
+    pub keyword: Option<String>,
+    pub last_sent_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name=digest_subscriptions)]
+pub struct NewDigestSubscription {
+    #[serde(skip_deserializing)]
+    pub user_id: i32,
+    pub email: String,
+    pub frequency: DigestFrequency,
+    pub rustacean_id: Option<i32>,
+    pub keyword: Option<String>,
+}
+
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Debug, Deserialize, Serialize)]
 #[diesel(sql_type=Text)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFrequency {
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    /// Size of the window of new crates covered by one digest.
+    pub fn hours(&self) -> i32 {
+        match self {
+            DigestFrequency::Daily => 24,
+            DigestFrequency::Weekly => 7 * 24,
+        }
+    }
+}
+
+impl std::fmt::Display for DigestFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DigestFrequency::Daily => write!(f, "daily"),
+            DigestFrequency::Weekly => write!(f, "weekly"),
+        }
+    }
+}
+
+impl FromStr for DigestFrequency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly),
+            _ => Err(format!("Unknown digest frequency '{}'", s)),
+        }
+    }
+}
+
+impl FromSql<Text, Pg> for DigestFrequency {
+    fn from_sql(value: PgValue) -> diesel::deserialize::Result<Self> {
+        match value.as_bytes() {
+            b"daily" => Ok(DigestFrequency::Daily),
+            b"weekly" => Ok(DigestFrequency::Weekly),
+            other => Err(format!(
+                "Unknown digest frequency '{}'",
+                String::from_utf8_lossy(other)
+            )
+            .into()),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for DigestFrequency {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {
+        match self {
+            DigestFrequency::Daily => out.write_all(b"daily")?,
+            DigestFrequency::Weekly => out.write_all(b"weekly")?,
+        };
+        Ok(IsNull::No)
+    }
+}
+
+/// Which crate events a user is emailed about, for the rustacean with the same `email`.
+#[derive(Queryable, Insertable, AsChangeset, Debug, Deserialize, Serialize)]
+#[diesel(table_name=notification_preferences)]
+#[diesel(primary_key(user_id))]
+#[diesel(treat_none_as_null = true)]
+pub struct NotificationPreferences {
+    #[serde(skip)]
+    pub user_id: i32,
+    pub email: String,
+    #[serde(default = "default_true")]
+    pub crate_updated: bool,
+    #[serde(default = "default_true")]
+    pub crate_deleted: bool,
+    /// Set once the user followed the link sent to `email`, until the email changes.
+    #[serde(skip_deserializing)]
+    pub email_verified_at: Option<NaiveDateTime>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct OutboxEmail {
+    pub id: i32,
+    pub sender: Option<String>,
+    pub recipients: Vec<String>,
+    pub subject: Option<String>,
+    #[serde(skip_serializing)]
+    pub message: Vec<u8>,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=email_outbox)]
+pub struct NewOutboxEmail {
+    pub sender: Option<String>,
+    pub recipients: Vec<String>,
+    pub subject: Option<String>,
+    pub message: Vec<u8>,
+}
+
+/// Delivery status of queued emails and webhook calls.
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Debug, PartialEq, Serialize)]
+#[diesel(sql_type=Text)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    Pending,
+    Sent,
+    Dead,
+}
+
+impl std::fmt::Display for OutboxStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutboxStatus::Pending => write!(f, "pending"),
+            OutboxStatus::Sent => write!(f, "sent"),
+            OutboxStatus::Dead => write!(f, "dead"),
+        }
+    }
+}
+
+impl FromStr for OutboxStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(OutboxStatus::Pending),
+            "sent" => Ok(OutboxStatus::Sent),
+            "dead" => Ok(OutboxStatus::Dead),
+            _ => Err(format!("Unknown outbox status '{}'", s)),
+        }
+    }
+}
+
+impl FromSql<Text, Pg> for OutboxStatus {
+    fn from_sql(value: PgValue) -> diesel::deserialize::Result<Self> {
+        Ok(String::from_utf8_lossy(value.as_bytes()).parse()?)
+    }
+}
+
+impl ToSql<Text, Pg> for OutboxStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Queryable, Identifiable, Debug, Serialize)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    /// Event names such as `crate.updated`, every event when empty.
+    pub events: Vec<String>,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl Webhook {
+    pub fn accepts(&self, event: &str) -> bool {
+        self.active && (self.events.is_empty() || self.events.iter().any(|e| e == event))
+    }
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name=webhooks)]
+pub struct NewWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Generated when left empty.
+    #[serde(default)]
+    pub secret: String,
+}
+
+/// Replacement of a webhook, the secret is kept unless one is given.
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name=webhooks)]
+pub struct WebhookChangeset {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub secret: Option<String>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+}
+
+#[derive(Queryable, Associations, Identifiable, Debug, Serialize)]
+#[diesel(belongs_to(Webhook))]
+#[diesel(table_name=webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=webhook_deliveries)]
+pub struct NewWebhookDelivery {
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// Last run of a scheduler job, shared by every server instance.
+#[derive(Queryable, Insertable, AsChangeset, Clone, Debug, Serialize)]
+#[diesel(table_name=scheduled_jobs)]
+#[diesel(treat_none_as_null = true)]
+pub struct JobRun {
+    pub name: String,
+    pub last_started_at: NaiveDateTime,
+    pub last_finished_at: Option<NaiveDateTime>,
+    pub last_status: String,
+    pub last_error: Option<String>,
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name=audit_events)]
+pub struct NewAuditEvent {
+    pub actor_id: Option<i32>,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+}
+
+/// Who performed a mutation, the CLI acts without a user or request.
+pub struct Actor {
+    pub user_id: Option<i32>,
+    pub request_id: Option<String>,
+}
+
+impl Actor {
+    pub fn cli() -> Self {
+        Actor {
+            user_id: None,
+            request_id: None,
+        }
+    }
+}
+
+/// Entities whose mutations are recorded in the audit log.
+pub trait Auditable: Serialize {
+    const ENTITY: &'static str;
+
+    fn entity_id(&self) -> i32;
+}
+
+impl Auditable for RolePermissions {
+    const ENTITY: &'static str = "role_permissions";
+
+    fn entity_id(&self) -> i32 {
+        self.role_id
+    }
+}
+
+impl Auditable for Rustacean {
+    const ENTITY: &'static str = "rustacean";
+
+    fn entity_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Auditable for Crate {
+    const ENTITY: &'static str = "crate";
+
+    fn entity_id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Auditable for User {
+    const ENTITY: &'static str = "user";
+
+    fn entity_id(&self) -> i32 {
+        self.id
+    }
+}
+
+#[derive(AsExpression, FromSqlRow, Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[diesel(sql_type=Text)]
+#[serde(rename_all = "lowercase")]
 pub enum RoleCode {
     Admin,
     Editor,
     Viewer,
 }
 
-impl ToString for RoleCode {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for RoleCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RoleCode::Admin => String::from("admin"),
-            RoleCode::Editor => String::from("editor"),
-            RoleCode::Viewer => String::from("viewer"),
+            RoleCode::Admin => write!(f, "admin"),
+            RoleCode::Editor => write!(f, "editor"),
+            RoleCode::Viewer => write!(f, "viewer"),
         }
     }
 }
 
+impl RoleCode {
+    pub const ALL: [RoleCode; 3] = [RoleCode::Admin, RoleCode::Editor, RoleCode::Viewer];
+}
+
 impl FromStr for RoleCode {
-    type Err = ();
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "admin" => Ok(RoleCode::Admin),
-            "editor" => Ok(RoleCode::Editor),
-            "viewer" => Ok(RoleCode::Viewer),
-            _ => Err(()),
-        }
+        RoleCode::ALL
+            .into_iter()
+            .find(|code| code.to_string() == s)
+            .ok_or_else(|| {
+                let codes: Vec<String> = RoleCode::ALL.iter().map(ToString::to_string).collect();
+                format!(
+                    "Unknown role code '{}', expected one of {}",
+                    s,
+                    codes.join(", ")
+                )
+            })
     }
 }
 
 impl FromSql<Text, Pg> for RoleCode {
     fn from_sql(value: PgValue) -> diesel::deserialize::Result<Self> {
-        match value.as_bytes() {
-            b"admin" => Ok(RoleCode::Admin),
-            b"editor" => Ok(RoleCode::Editor),
-            b"viewer" => Ok(RoleCode::Viewer),
-            _ => Ok(RoleCode::Viewer),
-        }
+        let code = std::str::from_utf8(value.as_bytes())?;
+        Ok(code.parse()?)
     }
 }
 
@@ -152,3 +582,12 @@ impl ToSql<Text, Pg> for RoleCode {
         Ok(IsNull::No)
     }
 }
+
+/// Active and idle connections, of the database as seen by Postgres or of a pool.
+#[derive(diesel::QueryableByName)]
+pub struct ConnectionCounts {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub active: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub idle: i64,
+}
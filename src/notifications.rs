@@ -0,0 +1,147 @@
+use std::error::Error;
+
+use chrono::{Datelike, Utc};
+use diesel::{PgConnection, QueryResult};
+use serde::Serialize;
+use tera::Context;
+
+use crate::auth;
+use crate::config;
+use crate::events::{Event, EventKind};
+use crate::mail::HtmlMailer;
+use crate::models::{Crate, NotificationPreferences, SessionUser};
+use crate::outbox;
+use crate::repositories::{NotificationPreferencesRepository, RustaceanRepository, UserRepository};
+
+const CRATE_UPDATED_TEMPLATE: &str = "email/crate_updated.html";
+const CRATE_DELETED_TEMPLATE: &str = "email/crate_deleted.html";
+const VERIFY_EMAIL_TEMPLATE: &str = "email/verify_email.html";
+const CHANGED_FIELDS: [&str; 4] = ["code", "name", "version", "description"];
+
+#[derive(Serialize)]
+struct FieldChange {
+    field: &'static str,
+    before: serde_json::Value,
+    after: serde_json::Value,
+}
+
+fn changes(before: &Crate, after: &Crate) -> Vec<FieldChange> {
+    let before = serde_json::json!(before);
+    let after = serde_json::json!(after);
+    CHANGED_FIELDS
+        .into_iter()
+        .filter(|field| before[field] != after[field])
+        .map(|field| FieldChange {
+            field,
+            before: before[field].clone(),
+            after: after[field].clone(),
+        })
+        .collect()
+}
+
+fn wants(preferences: &NotificationPreferences, kind: EventKind) -> bool {
+    match kind {
+        EventKind::Created => false,
+        EventKind::Updated => preferences.crate_updated,
+        EventKind::Deleted => preferences.crate_deleted,
+    }
+}
+
+/// Queues an email to the owner of a crate that was updated or deleted by someone else.
+///
+/// Owners are matched to users by the verified email of their notification preferences,
+/// which tells whether they opted out and whether they made the change themselves.
+/// Unverified claims on an email are ignored, so owners are notified of every change
+/// until its holder confirms it. Mail failures are logged rather than failing the mutation.
+pub fn notify_crate_owner(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    event: &Event<Crate>,
+) -> QueryResult<()> {
+    let (template_name, verb) = match event.kind {
+        EventKind::Created => return Ok(()),
+        EventKind::Updated => (CRATE_UPDATED_TEMPLATE, "updated"),
+        EventKind::Deleted => (CRATE_DELETED_TEMPLATE, "deleted"),
+    };
+    let a_crate = event.entity();
+    let owner = RustaceanRepository::find(connection, a_crate.rustacean_id)?;
+    let preferences =
+        NotificationPreferencesRepository::find_verified_by_email(connection, &owner.email)?;
+    let by_owner = event
+        .actor
+        .user_id
+        .is_some_and(|user_id| preferences.iter().any(|p| p.user_id == user_id));
+    if by_owner || preferences.iter().any(|p| !wants(p, event.kind)) {
+        return Ok(());
+    }
+
+    let editor = match event.actor.user_id {
+        Some(user_id) => UserRepository::find(connection, user_id)?.username,
+        None => "an administrator".to_string(),
+    };
+    let mut context = Context::new();
+    context.insert("crate", a_crate);
+    context.insert("owner", &owner);
+    context.insert("editor", &editor);
+    if let (Some(before), Some(after)) = (event.before, event.after) {
+        context.insert("changes", &changes(before, after));
+    }
+    context.insert("year", &Utc::now().year());
+
+    let subject = format!("Your crate {} was {}", a_crate.code, verb);
+    let messages = mailer.render_messages(
+        vec![owner.email.clone()],
+        Some(subject),
+        template_name,
+        &context,
+    );
+    match messages {
+        Ok(messages) => {
+            for message in &messages {
+                outbox::enqueue(connection, message)?;
+            }
+        }
+        Err(e) => log::error!(
+            "Cannot notify rustacean {} about crate {}: {}",
+            owner.id,
+            a_crate.id,
+            e
+        ),
+    }
+    Ok(())
+}
+
+/// Queues the email whose link confirms that the user receives mail at the email of
+/// their preferences, which only then apply to the crates of its owners.
+pub fn queue_email_verification(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    user: &SessionUser,
+    preferences: &NotificationPreferences,
+) -> Result<(), Box<dyn Error>> {
+    let token = auth::sign_email_verification_token(user.id, &preferences.email)?;
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("email", &preferences.email);
+    context.insert(
+        "verify_url",
+        &format!(
+            "{}/notifications/verify?token={}",
+            config::get().base_url.trim_end_matches('/'),
+            token
+        ),
+    );
+    context.insert("expires_in_hours", &auth::EMAIL_VERIFICATION_HOURS);
+    context.insert("year", &Utc::now().year());
+
+    let messages = mailer.render_messages(
+        vec![preferences.email.clone()],
+        Some("Confirm your notification email".to_string()),
+        VERIFY_EMAIL_TEMPLATE,
+        &context,
+    )?;
+    for message in &messages {
+        outbox::enqueue(connection, message)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::error::Error;
+
+use diesel::{Connection, PgConnection, QueryResult};
+use lettre::address::Envelope;
+use lettre::Message;
+
+use crate::mail::{self, MailTransport};
+use crate::models::{NewOutboxEmail, OutboxEmail};
+use crate::repositories::OutboxRepository;
+
+/// Attempts after which an email or webhook delivery is dead-lettered.
+pub const MAX_ATTEMPTS: i32 = 8;
+const BASE_BACKOFF_SECONDS: i32 = 30;
+const MAX_BACKOFF_SECONDS: i32 = 6 * 60 * 60;
+/// How long a claimed email stays hidden from other workers, past the SMTP timeouts.
+const LEASE_SECONDS: i32 = 5 * 60;
+
+/// Stores a message for delivery by `deliver_due`, within the caller's transaction.
+pub fn enqueue(connection: &mut PgConnection, message: &Message) -> QueryResult<OutboxEmail> {
+    let envelope = message.envelope();
+    OutboxRepository::enqueue(
+        connection,
+        NewOutboxEmail {
+            sender: envelope.from().map(|address| address.to_string()),
+            recipients: envelope.to().iter().map(|to| to.to_string()).collect(),
+            subject: message.headers().get_raw("Subject").map(String::from),
+            message: message.formatted(),
+        },
+    )
+}
+
+/// Seconds before the next attempt of a delivery that failed `attempts` times.
+pub fn backoff_seconds(attempts: i32) -> i32 {
+    let exponent = (attempts.clamp(1, 16) - 1) as u32;
+    BASE_BACKOFF_SECONDS
+        .saturating_mul(2_i32.pow(exponent))
+        .min(MAX_BACKOFF_SECONDS)
+}
+
+fn deliver(transport: &dyn MailTransport, email: &OutboxEmail) -> Result<(), Box<dyn Error>> {
+    let sender = email.sender.as_deref().map(str::parse).transpose()?;
+    let recipients = email
+        .recipients
+        .iter()
+        .map(|recipient| recipient.parse())
+        .collect::<Result<Vec<_>, _>>()?;
+    transport.deliver_raw(&Envelope::new(sender, recipients)?, &email.message)
+}
+
+#[derive(Debug, Default)]
+pub struct DeliveryReport {
+    pub sent: usize,
+    pub retried: usize,
+    pub dead: usize,
+}
+
+impl std::fmt::Display for DeliveryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} sent, {} queued for retry, {} dead-lettered",
+            self.sent, self.retried, self.dead
+        )
+    }
+}
+
+/// Delivers every due email, or those to `recipient` if set. Each one is claimed with a lease
+/// in a short transaction, so concurrent workers skip it, and sent with no transaction or lock
+/// held.
+pub fn deliver_due(
+    connection: &mut PgConnection,
+    transport: &dyn MailTransport,
+    recipient: Option<&str>,
+) -> QueryResult<DeliveryReport> {
+    let mut report = DeliveryReport::default();
+    loop {
+        let claimed = connection.transaction(|connection| {
+            let email = match OutboxRepository::lock_next_due(connection, recipient)? {
+                Some(email) => email,
+                None => return Ok(None),
+            };
+            OutboxRepository::lease(connection, email.id, LEASE_SECONDS)?;
+            Ok::<_, diesel::result::Error>(Some(email))
+        })?;
+        let email = match claimed {
+            Some(email) => email,
+            None => return Ok(report),
+        };
+        match deliver(transport, &email) {
+            Ok(()) => {
+                OutboxRepository::mark_sent(connection, email.id)?;
+                report.sent += 1;
+            }
+            Err(e) => {
+                let attempts = email.attempts + 1;
+                let retry_in_seconds =
+                    if attempts >= MAX_ATTEMPTS || mail::is_permanent_failure(e.as_ref()) {
+                        report.dead += 1;
+                        None
+                    } else {
+                        report.retried += 1;
+                        Some(backoff_seconds(attempts))
+                    };
+                log::warn!("Cannot deliver email #{}: {}", email.id, e);
+                OutboxRepository::mark_failed(
+                    connection,
+                    email.id,
+                    &e.to_string(),
+                    retry_in_seconds,
+                )?;
+            }
+        }
+    }
+}
@@ -1,12 +1,25 @@
+use chrono::NaiveDateTime;
 use diesel::dsl::{now, IntervalDsl};
 use diesel::prelude::*;
-use rocket_db_pools::deadpool_redis::redis::RedisError;
+use rocket_db_pools::deadpool_redis::redis::{self, RedisError};
+use serde::{Deserialize, Serialize};
 
-use crate::auth::SESSION_LIFE_TIME;
-use crate::models::{Crate, NewCrate, NewRustacean, RoleCode, Rustacean};
-use crate::models::{NewRole, NewUser, NewUserRole, Role, User, UserRole};
+use crate::config;
+use crate::models::{Actor, AuditEvent, Auditable, ConnectionCounts, NewAuditEvent};
+use crate::models::{
+    Crate, CratePatch, NewCrate, NewRustacean, RoleCode, Rustacean, RustaceanPatch,
+};
+use crate::models::{DigestFrequency, DigestSubscription, NewDigestSubscription};
+use crate::models::{JobRun, NewRole, NewUser, NewUserRole, Role, User, UserRole};
+use crate::models::{NewOutboxEmail, NotificationPreferences, OutboxEmail, OutboxStatus};
+use crate::models::{NewRolePermission, Permission, SessionUser};
+use crate::models::{NewWebhook, NewWebhookDelivery, Webhook, WebhookChangeset, WebhookDelivery};
 use crate::rocket_routes::CacheConnection;
-use crate::schema::{crates, roles, rustaceans, user_roles, users};
+use crate::schema::{
+    audit_events, crates, digest_subscriptions, email_outbox, notification_preferences,
+    permissions, role_permissions, roles, rustaceans, scheduled_jobs, user_roles, users,
+    webhook_deliveries, webhooks,
+};
 use rocket_db_pools::{deadpool_redis::redis::AsyncCommands, Connection};
 
 pub struct RustaceanRepository;
@@ -16,6 +29,25 @@ impl RustaceanRepository {
         rustaceans::table.find(id).get_result(connection)
     }
 
+    /// Locks the row until the surrounding transaction ends.
+    pub fn find_for_update(connection: &mut PgConnection, id: i32) -> QueryResult<Rustacean> {
+        rustaceans::table
+            .find(id)
+            .for_update()
+            .get_result(connection)
+    }
+
+    pub fn find_by_email(
+        connection: &mut PgConnection,
+        email: &str,
+    ) -> QueryResult<Option<Rustacean>> {
+        rustaceans::table
+            .filter(rustaceans::email.eq(email))
+            .order(rustaceans::id)
+            .first(connection)
+            .optional()
+    }
+
     pub fn find_multiple(connection: &mut PgConnection, limit: i64) -> QueryResult<Vec<Rustacean>> {
         rustaceans::table.limit(limit).load(connection)
     }
@@ -42,6 +74,19 @@ impl RustaceanRepository {
             .get_result(connection)
     }
 
+    pub fn patch(
+        connection: &mut PgConnection,
+        id: i32,
+        rustacean_patch: RustaceanPatch,
+    ) -> QueryResult<Rustacean> {
+        if rustacean_patch.is_empty() {
+            return Self::find(connection, id);
+        }
+        diesel::update(rustaceans::table.find(id))
+            .set(rustacean_patch)
+            .get_result(connection)
+    }
+
     pub fn delete(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
         diesel::delete(rustaceans::table.find(id)).execute(connection)
     }
@@ -50,17 +95,66 @@ impl RustaceanRepository {
 pub struct CrateRepository;
 
 impl CrateRepository {
-    pub fn find_since(connection: &mut PgConnection, hours_since: i32) -> QueryResult<Vec<Crate>> {
-        crates::table
-            .filter(crates::created_at.ge(now - hours_since.seconds()))
-            .order(crates::id.desc())
-            .load::<Crate>(connection)
+    /// Crates created in the last `hours_since` hours or listed in `updated_ids`, with their
+    /// author and whether they are new, narrowed to a rustacean and/or a keyword matched
+    /// against the crate code, name and description.
+    pub fn find_changed_since(
+        connection: &mut PgConnection,
+        hours_since: i32,
+        updated_ids: &[i32],
+        rustacean_id: Option<i32>,
+        keyword: Option<&str>,
+    ) -> QueryResult<Vec<(Crate, Rustacean, bool)>> {
+        let created_since = crates::created_at.ge(now - hours_since.hours());
+        let mut query = crates::table
+            .inner_join(rustaceans::table)
+            .filter(created_since.or(crates::id.eq_any(updated_ids.to_vec())))
+            .into_boxed();
+        if let Some(rustacean_id) = rustacean_id {
+            query = query.filter(crates::rustacean_id.eq(rustacean_id));
+        }
+        if let Some(keyword) = keyword {
+            let pattern = format!(
+                "%{}%",
+                keyword
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            query = query.filter(
+                crates::code
+                    .ilike(pattern.clone())
+                    .or(crates::name.ilike(pattern.clone()))
+                    .or(crates::description.ilike(pattern)),
+            );
+        }
+        query
+            .select((
+                crates::all_columns,
+                rustaceans::all_columns,
+                crates::created_at.ge(now - hours_since.hours()),
+            ))
+            .order((rustaceans::name, rustaceans::id, crates::id.desc()))
+            .load(connection)
     }
 
     pub fn find(connection: &mut PgConnection, id: i32) -> QueryResult<Crate> {
         crates::table.find(id).get_result(connection)
     }
 
+    /// Locks the row until the surrounding transaction ends.
+    pub fn find_for_update(connection: &mut PgConnection, id: i32) -> QueryResult<Crate> {
+        crates::table.find(id).for_update().get_result(connection)
+    }
+
+    pub fn find_by_code(connection: &mut PgConnection, code: &str) -> QueryResult<Option<Crate>> {
+        crates::table
+            .filter(crates::code.eq(code))
+            .order(crates::id)
+            .first(connection)
+            .optional()
+    }
+
     pub fn find_multiple(connection: &mut PgConnection, limit: i64) -> QueryResult<Vec<Crate>> {
         crates::table.limit(limit).load(connection)
     }
@@ -83,11 +177,26 @@ impl CrateRepository {
             .get_result(connection)
     }
 
+    pub fn patch(
+        connection: &mut PgConnection,
+        id: i32,
+        crate_patch: CratePatch,
+    ) -> QueryResult<Crate> {
+        if crate_patch.is_empty() {
+            return Self::find(connection, id);
+        }
+        diesel::update(crates::table.find(id))
+            .set(crate_patch)
+            .get_result(connection)
+    }
+
     pub fn delete(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
         diesel::delete(crates::table.find(id)).execute(connection)
     }
 }
 
+pub type UserWithRoles = (User, Vec<(UserRole, Role)>);
+
 pub struct UserRepository;
 
 impl UserRepository {
@@ -101,43 +210,43 @@ impl UserRepository {
             .get_result::<User>(c)?;
 
         for role_code in role_codes {
-            let new_user_role = {
-                if let Ok(role) = RoleRepository::find_by_code(c, &role_code) {
-                    NewUserRole {
-                        user_id: user.id,
-                        role_id: role.id,
-                    }
-                } else {
-                    let name = role_code.to_string();
-                    let new_role = NewRole {
-                        name: name,
-                        code: role_code,
-                    };
-                    let role = RoleRepository::create(c, new_role)?;
-                    NewUserRole {
-                        user_id: user.id,
-                        role_id: role.id,
-                    }
-                }
-            };
-
-            diesel::insert_into(user_roles::table)
-                .values(new_user_role)
-                .get_result::<UserRole>(c)?;
+            let role = RoleRepository::find_by_code(c, &role_code)?;
+            Self::assign_role(c, &user, &role)?;
         }
 
         Ok(user)
     }
 
+    pub fn assign_role(
+        connection: &mut PgConnection,
+        user: &User,
+        role: &Role,
+    ) -> QueryResult<UserRole> {
+        diesel::insert_into(user_roles::table)
+            .values(NewUserRole {
+                user_id: user.id,
+                role_id: role.id,
+            })
+            .get_result(connection)
+    }
+
+    pub fn update_password(
+        connection: &mut PgConnection,
+        id: i32,
+        password_hash: String,
+    ) -> QueryResult<User> {
+        diesel::update(users::table.find(id))
+            .set(users::password.eq(password_hash))
+            .get_result(connection)
+    }
+
     pub fn find_by_username(connection: &mut PgConnection, username: &String) -> QueryResult<User> {
         users::table
             .filter(users::username.eq(username))
             .first(connection)
     }
 
-    pub fn find_with_roles(
-        connection: &mut PgConnection,
-    ) -> QueryResult<Vec<(User, Vec<(UserRole, Role)>)>> {
+    pub fn find_with_roles(connection: &mut PgConnection) -> QueryResult<Vec<UserWithRoles>> {
         let users = users::table.load(connection)?;
         let user_roles = user_roles::table
             .inner_join(roles::table)
@@ -146,12 +255,19 @@ impl UserRepository {
         Ok(users.into_iter().zip(user_roles).collect())
     }
 
+    pub fn find_ids(connection: &mut PgConnection) -> QueryResult<Vec<i32>> {
+        users::table.select(users::id).load(connection)
+    }
+
     pub fn find(connection: &mut PgConnection, id: i32) -> QueryResult<User> {
         users::table.find(id).get_result(connection)
     }
 
     pub fn delete(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
         diesel::delete(user_roles::table.filter(user_roles::user_id.eq(id))).execute(connection)?;
+        diesel::delete(digest_subscriptions::table.filter(digest_subscriptions::user_id.eq(id)))
+            .execute(connection)?;
+        diesel::delete(notification_preferences::table.find(id)).execute(connection)?;
         diesel::delete(users::table.find(id)).execute(connection)
     }
 }
@@ -180,21 +296,729 @@ impl RoleRepository {
             .values(role)
             .get_result::<Role>(connection)
     }
+
+    pub fn find_all(connection: &mut PgConnection) -> QueryResult<Vec<Role>> {
+        roles::table.order(roles::id).load(connection)
+    }
+}
+
+pub struct PermissionRepository;
+
+impl PermissionRepository {
+    pub fn find_all(connection: &mut PgConnection) -> QueryResult<Vec<Permission>> {
+        permissions::table.order(permissions::id).load(connection)
+    }
+
+    pub fn find_by_codes(
+        connection: &mut PgConnection,
+        codes: &[String],
+    ) -> QueryResult<Vec<Permission>> {
+        permissions::table
+            .filter(permissions::code.eq_any(codes))
+            .order(permissions::id)
+            .load(connection)
+    }
+
+    pub fn find_by_role(
+        connection: &mut PgConnection,
+        role: &Role,
+    ) -> QueryResult<Vec<Permission>> {
+        permissions::table
+            .inner_join(role_permissions::table)
+            .filter(role_permissions::role_id.eq(role.id))
+            .select(permissions::all_columns)
+            .order(permissions::id)
+            .load(connection)
+    }
+
+    /// Codes of the permissions granted to any of the roles.
+    pub fn find_codes_by_roles(
+        connection: &mut PgConnection,
+        role_codes: &[RoleCode],
+    ) -> QueryResult<Vec<String>> {
+        permissions::table
+            .inner_join(role_permissions::table.inner_join(roles::table))
+            .filter(roles::code.eq_any(role_codes))
+            .select(permissions::code)
+            .distinct()
+            .order(permissions::code)
+            .load(connection)
+    }
+
+    /// Grants exactly `permissions` to the role, revoking the others.
+    pub fn replace_for_role(
+        connection: &mut PgConnection,
+        role: &Role,
+        permissions: &[Permission],
+    ) -> QueryResult<()> {
+        diesel::delete(role_permissions::table.filter(role_permissions::role_id.eq(role.id)))
+            .execute(connection)?;
+        let rows: Vec<NewRolePermission> = permissions
+            .iter()
+            .map(|permission| NewRolePermission {
+                role_id: role.id,
+                permission_id: permission.id,
+            })
+            .collect();
+        diesel::insert_into(role_permissions::table)
+            .values(rows)
+            .execute(connection)?;
+        Ok(())
+    }
+}
+
+pub struct DigestSubscriptionRepository;
+
+impl DigestSubscriptionRepository {
+    pub fn find_by_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<DigestSubscription>> {
+        digest_subscriptions::table
+            .filter(digest_subscriptions::user_id.eq(user_id))
+            .order(digest_subscriptions::id)
+            .load(connection)
+    }
+
+    pub fn find_multiple(
+        connection: &mut PgConnection,
+        frequency: Option<DigestFrequency>,
+    ) -> QueryResult<Vec<DigestSubscription>> {
+        let mut query = digest_subscriptions::table.into_boxed();
+        if let Some(frequency) = frequency {
+            query = query.filter(digest_subscriptions::frequency.eq(frequency));
+        }
+        query.order(digest_subscriptions::id).load(connection)
+    }
+
+    pub fn create(
+        connection: &mut PgConnection,
+        new_subscription: NewDigestSubscription,
+    ) -> QueryResult<DigestSubscription> {
+        diesel::insert_into(digest_subscriptions::table)
+            .values(new_subscription)
+            .get_result(connection)
+    }
+
+    pub fn mark_sent(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
+        diesel::update(digest_subscriptions::table.find(id))
+            .set(digest_subscriptions::last_sent_at.eq(now))
+            .execute(connection)
+    }
+
+    pub fn delete(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
+        diesel::delete(digest_subscriptions::table.find(id)).execute(connection)
+    }
+
+    pub fn delete_for_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+        id: i32,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            digest_subscriptions::table
+                .filter(digest_subscriptions::user_id.eq(user_id))
+                .find(id),
+        )
+        .execute(connection)
+    }
+}
+
+pub struct NotificationPreferencesRepository;
+
+impl NotificationPreferencesRepository {
+    pub fn find_by_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<NotificationPreferences>> {
+        notification_preferences::table
+            .find(user_id)
+            .first(connection)
+            .optional()
+    }
+
+    /// Preferences of every user who verified `email`, compared case-insensitively.
+    pub fn find_verified_by_email(
+        connection: &mut PgConnection,
+        email: &str,
+    ) -> QueryResult<Vec<NotificationPreferences>> {
+        notification_preferences::table
+            .filter(lower(notification_preferences::email).eq(email.to_lowercase()))
+            .filter(notification_preferences::email_verified_at.is_not_null())
+            .load(connection)
+    }
+
+    pub fn mark_verified(connection: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::update(notification_preferences::table.find(user_id))
+            .set(notification_preferences::email_verified_at.eq(now))
+            .execute(connection)
+    }
+
+    pub fn save(
+        connection: &mut PgConnection,
+        preferences: &NotificationPreferences,
+    ) -> QueryResult<NotificationPreferences> {
+        diesel::insert_into(notification_preferences::table)
+            .values(preferences)
+            .on_conflict(notification_preferences::user_id)
+            .do_update()
+            .set(preferences)
+            .get_result(connection)
+    }
+}
+
+pub struct OutboxRepository;
+
+impl OutboxRepository {
+    pub fn find_multiple(
+        connection: &mut PgConnection,
+        status: Option<OutboxStatus>,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEmail>> {
+        let mut query = email_outbox::table.into_boxed();
+        if let Some(status) = status {
+            query = query.filter(email_outbox::status.eq(status));
+        }
+        query
+            .order(email_outbox::id.desc())
+            .limit(limit)
+            .load(connection)
+    }
+
+    pub fn enqueue(
+        connection: &mut PgConnection,
+        new_email: NewOutboxEmail,
+    ) -> QueryResult<OutboxEmail> {
+        diesel::insert_into(email_outbox::table)
+            .values(new_email)
+            .get_result(connection)
+    }
+
+    /// Locks the oldest pending email that is due, skipping those locked by other workers,
+    /// among those to `recipient` if set.
+    pub fn lock_next_due(
+        connection: &mut PgConnection,
+        recipient: Option<&str>,
+    ) -> QueryResult<Option<OutboxEmail>> {
+        // Every array contains the empty one, which matches any recipient
+        let recipients: Vec<String> = recipient.map(String::from).into_iter().collect();
+        email_outbox::table
+            .filter(email_outbox::status.eq(OutboxStatus::Pending))
+            .filter(email_outbox::next_attempt_at.le(now))
+            .filter(email_outbox::recipients.contains(recipients))
+            .order(email_outbox::id)
+            .for_update()
+            .skip_locked()
+            .first(connection)
+            .optional()
+    }
+
+    /// Hides a claimed email from other workers for `seconds`, it is due again if the worker
+    /// dies before recording the outcome.
+    pub fn lease(connection: &mut PgConnection, id: i32, seconds: i32) -> QueryResult<usize> {
+        diesel::update(email_outbox::table.find(id))
+            .set(email_outbox::next_attempt_at.eq(now + seconds.seconds()))
+            .execute(connection)
+    }
+
+    pub fn mark_sent(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
+        diesel::update(email_outbox::table.find(id))
+            .set((
+                email_outbox::status.eq(OutboxStatus::Sent),
+                email_outbox::attempts.eq(email_outbox::attempts + 1),
+                email_outbox::sent_at.eq(now),
+            ))
+            .execute(connection)
+    }
+
+    /// Records a failed attempt, the email is dead-lettered when it is not retried.
+    pub fn mark_failed(
+        connection: &mut PgConnection,
+        id: i32,
+        error: &str,
+        retry_in_seconds: Option<i32>,
+    ) -> QueryResult<usize> {
+        let status = match retry_in_seconds {
+            Some(_) => OutboxStatus::Pending,
+            None => OutboxStatus::Dead,
+        };
+        let query = diesel::update(email_outbox::table.find(id));
+        let values = (
+            email_outbox::status.eq(status),
+            email_outbox::attempts.eq(email_outbox::attempts + 1),
+            email_outbox::last_error.eq(error),
+        );
+        match retry_in_seconds {
+            Some(seconds) => query
+                .set((
+                    values,
+                    email_outbox::next_attempt_at.eq(now + seconds.seconds()),
+                ))
+                .execute(connection),
+            None => query.set(values).execute(connection),
+        }
+    }
+
+    /// Puts failed emails back in the queue with a fresh attempt budget, all dead ones without an id.
+    pub fn requeue(connection: &mut PgConnection, id: Option<i32>) -> QueryResult<usize> {
+        let values = (
+            email_outbox::status.eq(OutboxStatus::Pending),
+            email_outbox::attempts.eq(0),
+            email_outbox::next_attempt_at.eq(now),
+        );
+        match id {
+            Some(id) => diesel::update(
+                email_outbox::table
+                    .find(id)
+                    .filter(email_outbox::status.ne(OutboxStatus::Sent)),
+            )
+            .set(values)
+            .execute(connection),
+            None => diesel::update(
+                email_outbox::table.filter(email_outbox::status.eq(OutboxStatus::Dead)),
+            )
+            .set(values)
+            .execute(connection),
+        }
+    }
+}
+
+pub struct WebhookRepository;
+
+impl WebhookRepository {
+    pub fn find(connection: &mut PgConnection, id: i32) -> QueryResult<Webhook> {
+        webhooks::table.find(id).get_result(connection)
+    }
+
+    pub fn find_multiple(connection: &mut PgConnection) -> QueryResult<Vec<Webhook>> {
+        webhooks::table.order(webhooks::id).load(connection)
+    }
+
+    pub fn find_active(connection: &mut PgConnection) -> QueryResult<Vec<Webhook>> {
+        webhooks::table
+            .filter(webhooks::active.eq(true))
+            .order(webhooks::id)
+            .load(connection)
+    }
+
+    pub fn create(connection: &mut PgConnection, new_webhook: NewWebhook) -> QueryResult<Webhook> {
+        diesel::insert_into(webhooks::table)
+            .values(new_webhook)
+            .get_result(connection)
+    }
+
+    pub fn update(
+        connection: &mut PgConnection,
+        id: i32,
+        webhook: WebhookChangeset,
+    ) -> QueryResult<Webhook> {
+        diesel::update(webhooks::table.find(id))
+            .set(webhook)
+            .get_result(connection)
+    }
+
+    /// Deletes the webhook along with its deliveries.
+    pub fn delete(connection: &mut PgConnection, id: i32) -> QueryResult<usize> {
+        diesel::delete(webhooks::table.find(id)).execute(connection)
+    }
+}
+
+pub struct WebhookDeliveryRepository;
+
+impl WebhookDeliveryRepository {
+    pub fn find_by_webhook(
+        connection: &mut PgConnection,
+        webhook: &Webhook,
+        limit: i64,
+    ) -> QueryResult<Vec<WebhookDelivery>> {
+        WebhookDelivery::belonging_to(webhook)
+            .order(webhook_deliveries::id.desc())
+            .limit(limit)
+            .load(connection)
+    }
+
+    pub fn create(
+        connection: &mut PgConnection,
+        new_deliveries: Vec<NewWebhookDelivery>,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(webhook_deliveries::table)
+            .values(new_deliveries)
+            .execute(connection)
+    }
+
+    /// Locks the oldest pending delivery that is due, skipping those locked by other workers.
+    pub fn lock_next_due(connection: &mut PgConnection) -> QueryResult<Option<WebhookDelivery>> {
+        webhook_deliveries::table
+            .filter(webhook_deliveries::status.eq(OutboxStatus::Pending))
+            .filter(webhook_deliveries::next_attempt_at.le(now))
+            .order(webhook_deliveries::id)
+            .for_update()
+            .skip_locked()
+            .first(connection)
+            .optional()
+    }
+
+    /// Hides a claimed delivery from other workers for `seconds`, it is due again if the
+    /// worker dies before recording the outcome.
+    pub fn lease(connection: &mut PgConnection, id: i32, seconds: i32) -> QueryResult<usize> {
+        diesel::update(webhook_deliveries::table.find(id))
+            .set(webhook_deliveries::next_attempt_at.eq(now + seconds.seconds()))
+            .execute(connection)
+    }
+
+    pub fn mark_sent(
+        connection: &mut PgConnection,
+        id: i32,
+        response_status: i32,
+    ) -> QueryResult<usize> {
+        diesel::update(webhook_deliveries::table.find(id))
+            .set((
+                webhook_deliveries::status.eq(OutboxStatus::Sent),
+                webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+                webhook_deliveries::response_status.eq(response_status),
+                webhook_deliveries::last_error.eq(None::<String>),
+                webhook_deliveries::delivered_at.eq(now),
+            ))
+            .execute(connection)
+    }
+
+    /// Records a failed attempt, the delivery is dead-lettered when it is not retried.
+    pub fn mark_failed(
+        connection: &mut PgConnection,
+        id: i32,
+        response_status: Option<i32>,
+        error: &str,
+        retry_in_seconds: Option<i32>,
+    ) -> QueryResult<usize> {
+        let status = match retry_in_seconds {
+            Some(_) => OutboxStatus::Pending,
+            None => OutboxStatus::Dead,
+        };
+        let query = diesel::update(webhook_deliveries::table.find(id));
+        let values = (
+            webhook_deliveries::status.eq(status),
+            webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+            webhook_deliveries::response_status.eq(response_status),
+            webhook_deliveries::last_error.eq(error),
+        );
+        match retry_in_seconds {
+            Some(seconds) => query
+                .set((
+                    values,
+                    webhook_deliveries::next_attempt_at.eq(now + seconds.seconds()),
+                ))
+                .execute(connection),
+            None => query.set(values).execute(connection),
+        }
+    }
+
+    /// Queues a delivery of the webhook again, whatever its status, with a fresh attempt budget.
+    pub fn redeliver(
+        connection: &mut PgConnection,
+        webhook_id: i32,
+        id: i32,
+    ) -> QueryResult<WebhookDelivery> {
+        diesel::update(
+            webhook_deliveries::table
+                .find(id)
+                .filter(webhook_deliveries::webhook_id.eq(webhook_id)),
+        )
+        .set((
+            webhook_deliveries::status.eq(OutboxStatus::Pending),
+            webhook_deliveries::attempts.eq(0),
+            webhook_deliveries::next_attempt_at.eq(now),
+        ))
+        .get_result(connection)
+    }
+}
+
+diesel::sql_function!(fn lower(value: diesel::sql_types::Text) -> diesel::sql_types::Text);
+diesel::sql_function!(fn hashtext(value: diesel::sql_types::Text) -> diesel::sql_types::Integer);
+diesel::sql_function!(fn pg_try_advisory_lock(namespace: diesel::sql_types::Integer, key: diesel::sql_types::Integer) -> diesel::sql_types::Bool);
+diesel::sql_function!(fn pg_advisory_unlock(namespace: diesel::sql_types::Integer, key: diesel::sql_types::Integer) -> diesel::sql_types::Bool);
+
+/// Namespace of the advisory locks taken by scheduler jobs.
+const JOB_LOCK_NAMESPACE: i32 = 0x6372_3873;
+/// Advisory lock held while migrations run, distinct from the job locks.
+const MIGRATION_LOCK: (i32, i32) = (0x6372_386d, 0);
+
+pub struct JobRunRepository;
+
+impl JobRunRepository {
+    pub fn find(connection: &mut PgConnection, name: &str) -> QueryResult<Option<JobRun>> {
+        scheduled_jobs::table
+            .find(name)
+            .get_result(connection)
+            .optional()
+    }
+
+    pub fn find_all(connection: &mut PgConnection) -> QueryResult<Vec<JobRun>> {
+        scheduled_jobs::table
+            .order(scheduled_jobs::name)
+            .load(connection)
+    }
+
+    /// Takes a session scoped advisory lock on the job, false when another instance holds it.
+    /// It outlives transactions so the job can commit its work item by item, see `unlock`.
+    pub fn try_lock(connection: &mut PgConnection, name: &str) -> QueryResult<bool> {
+        diesel::select(pg_try_advisory_lock(JOB_LOCK_NAMESPACE, hashtext(name)))
+            .get_result(connection)
+    }
+
+    pub fn unlock(connection: &mut PgConnection, name: &str) -> QueryResult<bool> {
+        diesel::select(pg_advisory_unlock(JOB_LOCK_NAMESPACE, hashtext(name)))
+            .get_result(connection)
+    }
+
+    pub fn save(connection: &mut PgConnection, job_run: &JobRun) -> QueryResult<JobRun> {
+        diesel::insert_into(scheduled_jobs::table)
+            .values(job_run)
+            .on_conflict(scheduled_jobs::name)
+            .do_update()
+            .set(job_run)
+            .get_result(connection)
+    }
+}
+
+diesel::table! {
+    /// Bookkeeping table of the Diesel migrations.
+    __diesel_schema_migrations (version) {
+        version -> VarChar,
+        run_on -> Timestamp,
+    }
+}
+
+pub struct DatabaseRepository;
+
+impl DatabaseRepository {
+    pub fn ping(connection: &mut PgConnection) -> QueryResult<()> {
+        diesel::sql_query("SELECT 1")
+            .execute(connection)
+            .map(|_| ())
+    }
+
+    /// Version of the last applied migration, e.g. `20261019140000`.
+    pub fn migration_version(connection: &mut PgConnection) -> QueryResult<Option<String>> {
+        __diesel_schema_migrations::table
+            .select(diesel::dsl::max(__diesel_schema_migrations::version))
+            .get_result(connection)
+    }
+
+    /// Waits for the session scoped advisory lock of the migrations, so that instances
+    /// starting together apply or revert them one after the other. Migrations run in their own
+    /// transactions, a transaction scoped lock would not cover them all.
+    pub fn lock_migrations(connection: &mut PgConnection) -> QueryResult<()> {
+        diesel::sql_query("SELECT pg_advisory_lock($1, $2)")
+            .bind::<diesel::sql_types::Integer, _>(MIGRATION_LOCK.0)
+            .bind::<diesel::sql_types::Integer, _>(MIGRATION_LOCK.1)
+            .execute(connection)
+            .map(|_| ())
+    }
+
+    pub fn unlock_migrations(connection: &mut PgConnection) -> QueryResult<()> {
+        diesel::sql_query("SELECT pg_advisory_unlock($1, $2)")
+            .bind::<diesel::sql_types::Integer, _>(MIGRATION_LOCK.0)
+            .bind::<diesel::sql_types::Integer, _>(MIGRATION_LOCK.1)
+            .execute(connection)
+            .map(|_| ())
+    }
+
+    /// Connections of every client of the database, the pool included.
+    pub fn connection_counts(connection: &mut PgConnection) -> QueryResult<ConnectionCounts> {
+        diesel::sql_query(
+            "SELECT count(*) FILTER (WHERE state <> 'idle') AS active, \
+                    count(*) FILTER (WHERE state = 'idle') AS idle \
+             FROM pg_stat_activity WHERE datname = current_database()",
+        )
+        .get_result(connection)
+    }
+}
+
+pub struct AuditRepository;
+
+impl AuditRepository {
+    /// Records a mutation, `before` is empty on create and `after` on delete.
+    pub fn record<T: Auditable>(
+        connection: &mut PgConnection,
+        actor: &Actor,
+        action: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> QueryResult<AuditEvent> {
+        let entity_id = before.or(after).map(|e| e.entity_id()).unwrap_or_default();
+        let new_event = NewAuditEvent {
+            actor_id: actor.user_id,
+            entity: T::ENTITY.to_string(),
+            entity_id,
+            action: action.to_string(),
+            before: before.map(|e| serde_json::json!(e)),
+            after: after.map(|e| serde_json::json!(e)),
+            request_id: actor.request_id.clone(),
+        };
+        diesel::insert_into(audit_events::table)
+            .values(new_event)
+            .get_result(connection)
+    }
+
+    /// Ids of the crates whose version changed in the last `hours_since` hours.
+    pub fn find_crate_version_changes(
+        connection: &mut PgConnection,
+        hours_since: i32,
+    ) -> QueryResult<Vec<i32>> {
+        audit_events::table
+            .filter(audit_events::entity.eq(Crate::ENTITY))
+            .filter(audit_events::action.eq("update"))
+            .filter(audit_events::created_at.ge(now - hours_since.hours()))
+            .filter(diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "before->>'version' <> after->>'version'",
+            ))
+            .select(audit_events::entity_id)
+            .distinct()
+            .order(audit_events::entity_id)
+            .load(connection)
+    }
+
+    pub fn find_multiple(
+        connection: &mut PgConnection,
+        entity: Option<String>,
+        actor_id: Option<i32>,
+        since: Option<NaiveDateTime>,
+        limit: i64,
+    ) -> QueryResult<Vec<AuditEvent>> {
+        let mut query = audit_events::table.into_boxed();
+        if let Some(entity) = entity {
+            query = query.filter(audit_events::entity.eq(entity));
+        }
+        if let Some(actor_id) = actor_id {
+            query = query.filter(audit_events::actor_id.eq(actor_id));
+        }
+        if let Some(since) = since {
+            query = query.filter(audit_events::created_at.ge(since));
+        }
+        query
+            .order(audit_events::id.desc())
+            .limit(limit)
+            .load(connection)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPermissions {
+    generation: i64,
+    permissions: Vec<String>,
 }
 
 pub struct SessionRepository;
 
 impl SessionRepository {
-    pub async fn cache_session_id(
+    /// Stores the session and indexes it under the user so that it can be revoked.
+    pub async fn cache_session(
         session_id: &String,
-        user_id: i32,
+        user: &SessionUser,
         mut cache: Connection<CacheConnection>,
     ) -> Result<(), RedisError> {
+        let lifetime_seconds = config::get().sessions.lifetime_seconds;
+        let index = format!("users/{}/sessions", user.id);
         cache
             .set_ex::<_, _, ()>(
                 format!("sessions/{}", session_id),
-                user_id,
-                SESSION_LIFE_TIME,
+                serde_json::json!(user).to_string(),
+                lifetime_seconds,
+            )
+            .await?;
+        cache.sadd::<_, _, ()>(&index, session_id).await?;
+        cache.expire::<_, ()>(&index, lifetime_seconds).await
+    }
+
+    pub async fn find_session(
+        session_id: &str,
+        cache: &mut Connection<CacheConnection>,
+    ) -> Result<Option<SessionUser>, RedisError> {
+        let record = cache
+            .get::<_, Option<String>>(format!("sessions/{}", session_id))
+            .await?;
+        // Unreadable records, e.g. of an older format, are logged out
+        Ok(record.and_then(|record| serde_json::from_str(&record).ok()))
+    }
+
+    /// Ends every session of the user, their roles or credentials having changed.
+    pub fn revoke_user_sessions(
+        cache: &mut redis::Connection,
+        user_id: i32,
+    ) -> Result<(), RedisError> {
+        let index = format!("users/{}/sessions", user_id);
+        let session_ids: Vec<String> = redis::Commands::smembers(cache, &index)?;
+        let mut keys = vec![index];
+        for session_id in session_ids {
+            keys.push(format!("sessions/{}/permissions", session_id));
+            keys.push(format!("sessions/{}", session_id));
+        }
+        redis::Commands::del(cache, keys)
+    }
+
+    /// Ends the sessions of every user, the users having been dropped with the database.
+    pub fn revoke_all_sessions(cache: &mut redis::Connection) -> Result<(), RedisError> {
+        let mut keys: Vec<String> = redis::Commands::keys(cache, "sessions/*")?;
+        keys.extend(redis::Commands::keys::<_, Vec<String>>(
+            cache,
+            "users/*/sessions",
+        )?);
+        if keys.is_empty() {
+            return Ok(());
+        }
+        redis::Commands::del(cache, keys)
+    }
+
+    /// Generation of the role permissions, bumped on every edit so that sessions reload them.
+    pub async fn permissions_generation(
+        cache: &mut Connection<CacheConnection>,
+    ) -> Result<i64, RedisError> {
+        cache
+            .get::<_, Option<i64>>("permissions/generation")
+            .await
+            .map(Option::unwrap_or_default)
+    }
+
+    pub async fn invalidate_permissions(
+        cache: &mut Connection<CacheConnection>,
+    ) -> Result<(), RedisError> {
+        redis::cmd("INCR")
+            .arg("permissions/generation")
+            .query_async::<_, ()>(&mut **cache)
+            .await
+    }
+
+    /// Permission codes of the session, if cached for the current `generation`.
+    pub async fn find_permissions(
+        session_id: &str,
+        generation: i64,
+        cache: &mut Connection<CacheConnection>,
+    ) -> Result<Option<Vec<String>>, RedisError> {
+        let cached = cache
+            .get::<_, Option<String>>(format!("sessions/{}/permissions", session_id))
+            .await?;
+        // Unreadable and outdated entries are misses, they get overwritten
+        Ok(cached
+            .and_then(|cached| serde_json::from_str::<CachedPermissions>(&cached).ok())
+            .filter(|cached| cached.generation == generation)
+            .map(|cached| cached.permissions))
+    }
+
+    pub async fn cache_permissions(
+        session_id: &str,
+        generation: i64,
+        permissions: Vec<String>,
+        cache: &mut Connection<CacheConnection>,
+    ) -> Result<(), RedisError> {
+        let cached = CachedPermissions {
+            generation,
+            permissions,
+        };
+        cache
+            .set_ex::<_, _, ()>(
+                format!("sessions/{}/permissions", session_id),
+                serde_json::json!(cached).to_string(),
+                config::get().sessions.lifetime_seconds,
             )
             .await
     }
@@ -0,0 +1,67 @@
+use std::fmt::Display;
+use std::io::Write;
+
+use chrono::{SecondsFormat, Utc};
+use log::Level;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::json::{json, Value};
+use rocket::{Data, Request, Response};
+
+use crate::metrics::UNMATCHED_ROUTE;
+use crate::rocket_routes::{AuthenticatedUserId, RequestId, RequestStart, REQUEST_ID_HEADER};
+
+/// Writes `record` as a line on stdout, stamped with the time and its kind. Going around
+/// Rocket's logger keeps the lines parseable: it drops records below its `log_level` and
+/// colours the others in debug builds.
+fn write_record(kind: &str, mut record: Value) {
+    record["timestamp"] = json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true));
+    record["kind"] = json!(kind);
+    let _ = writeln!(std::io::stdout().lock(), "{}", record);
+}
+
+/// Logs what happened while handling a request as a JSON line carrying its id.
+pub fn log_request(level: Level, request_id: &RequestId, message: impl Display) {
+    write_record(
+        "request",
+        json!({
+            "level": level.as_str().to_lowercase(),
+            "request_id": request_id.to_string(),
+            "message": message.to_string(),
+        }),
+    );
+}
+
+/// Echoes the request id in `X-Request-Id` and logs every request as a JSON line.
+pub struct AccessLog;
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids and access log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        RequestStart::from_request_cache(req);
+        RequestId::from_request_cache(req);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let request_id = RequestId::from_request_cache(req);
+        res.set_raw_header(REQUEST_ID_HEADER, request_id.to_string());
+
+        let latency = RequestStart::from_request_cache(req).0.elapsed();
+        let entry = json!({
+            "request_id": request_id.to_string(),
+            "method": req.method().as_str(),
+            "path": req.uri().path().as_str(),
+            "route": req.route().map(|route| route.uri.path()).unwrap_or(UNMATCHED_ROUTE),
+            "status": res.status().code,
+            "latency_ms": latency.as_micros() as f64 / 1000.0,
+            "user_id": AuthenticatedUserId::from_request_cache(req).0,
+        });
+        write_record("access", entry);
+    }
+}
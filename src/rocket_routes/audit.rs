@@ -0,0 +1,46 @@
+use chrono::{DateTime, NaiveDateTime};
+use rocket::serde::json::{json, Value};
+
+use crate::{
+    config,
+    repositories::AuditRepository,
+    rocket_routes::{AuditRead, Authorized, DbConnection},
+};
+
+use super::ApiError;
+
+fn parse_since(since: &str) -> Result<NaiveDateTime, ApiError> {
+    DateTime::parse_from_rfc3339(since)
+        .map(|since| since.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(since, "%Y-%m-%dT%H:%M:%S%.f"))
+        .map_err(|_| {
+            ApiError::BadRequest(format!(
+                "Invalid since timestamp '{}', expected RFC 3339",
+                since
+            ))
+        })
+}
+
+#[rocket::get("/audit?<entity>&<actor>&<since>&<limit>")]
+pub async fn get_audit_events(
+    db: DbConnection,
+    entity: Option<String>,
+    actor: Option<i32>,
+    since: Option<String>,
+    limit: Option<i64>,
+    _user: Authorized<AuditRead>,
+) -> Result<Value, ApiError> {
+    let since = since.as_deref().map(parse_since).transpose()?;
+    db.run(move |connection| {
+        AuditRepository::find_multiple(
+            connection,
+            entity,
+            actor,
+            since,
+            limit.unwrap_or(config::get().page_limits.audit_events),
+        )
+        .map(|events| json!(events))
+        .map_err(ApiError::from)
+    })
+    .await
+}
@@ -1,15 +1,12 @@
-use super::{server_error, DbConnection};
+use super::{ApiError, DbConnection};
 use crate::{
     auth::{self, Credentials},
-    models::User,
-    repositories::{SessionRepository, UserRepository},
+    metrics,
+    models::SessionUser,
+    repositories::{RoleRepository, SessionRepository, UserRepository},
     rocket_routes::CacheConnection,
 };
-use rocket::{
-    http::Status,
-    response::status::Custom,
-    serde::json::{serde_json::json, Json, Value},
-};
+use rocket::serde::json::{serde_json::json, Json, Value};
 use rocket_db_pools::Connection;
 
 #[rocket::post("/login", format = "json", data = "<credentials>")]
@@ -17,29 +14,59 @@ pub async fn login(
     credentials: Json<Credentials>,
     db: DbConnection,
     cache: Connection<CacheConnection>,
-) -> Result<Value, Custom<Value>> {
+) -> Result<Value, ApiError> {
+    let result = authenticate(credentials, db, cache).await;
+    metrics::record_login(result.is_ok());
+    result
+}
+
+async fn authenticate(
+    credentials: Json<Credentials>,
+    db: DbConnection,
+    cache: Connection<CacheConnection>,
+) -> Result<Value, ApiError> {
     let username = credentials.username.clone();
     let user = db
         .run(move |connection| {
             UserRepository::find_by_username(connection, &username).map_err(|e| match e {
                 diesel::result::Error::NotFound => {
-                    Custom(Status::Unauthorized, json!("Wrong credentials"))
+                    ApiError::Unauthorized("Wrong credentials".to_string())
                 }
-                _ => server_error(e.into()),
+                _ => e.into(),
             })
         })
         .await?;
 
     let session_id = auth::authorize_user(&user, &credentials)
-        .map_err(|_| Custom(Status::Unauthorized, json!("Wrong credentials")))?;
+        .map_err(|_| ApiError::Unauthorized("Wrong credentials".to_string()))?;
 
-    SessionRepository::cache_session_id(&session_id, user.id, cache)
+    let session_user = db
+        .run(move |connection| {
+            RoleRepository::find_by_user(connection, &user).map(|roles| SessionUser {
+                id: user.id,
+                username: user.username,
+                roles: roles.into_iter().map(|role| role.code).collect(),
+            })
+        })
+        .await?;
+    SessionRepository::cache_session(&session_id, &session_user, cache)
         .await
         .map(|_| json!({ "token": session_id }))
-        .map_err(|e| server_error(e.into()))
+        .map_err(ApiError::from)
 }
 
 #[rocket::get("/me")]
-pub fn me(user: User) -> Value {
-    json!(user)
+pub async fn me(db: DbConnection, session_user: SessionUser) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        let user = UserRepository::find(connection, session_user.id).map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                ApiError::Unauthorized("User no longer exists".to_string())
+            }
+            _ => e.into(),
+        })?;
+        let mut body = json!(user);
+        body["roles"] = json!(session_user.roles);
+        Ok(body)
+    })
+    .await
 }
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Method, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+use crate::config::{self, CorsConfig};
+
+use super::ApiError;
+
+const ORIGIN: &str = "Origin";
+const REQUEST_METHOD: &str = "Access-Control-Request-Method";
+const REQUEST_HEADERS: &str = "Access-Control-Request-Headers";
+
+/// Whether `origin` matches `pattern`: `*`, an exact origin such as `https://cr8s.com`,
+/// or `https://*.cr8s.com` for any of its subdomains (but not the domain itself).
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.split_once("*.") {
+        Some((scheme, domain)) => {
+            origin
+                .get(..scheme.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+                && origin[scheme.len()..]
+                    .to_ascii_lowercase()
+                    .strip_suffix(&domain.to_ascii_lowercase())
+                    .and_then(|subdomain| subdomain.strip_suffix('.'))
+                    .is_some_and(|subdomain| {
+                        !subdomain.is_empty() && !subdomain.contains(['/', ':', '@'])
+                    })
+        }
+        None => pattern.eq_ignore_ascii_case(origin),
+    }
+}
+
+fn origin_allowed(cors: &CorsConfig, origin: &str) -> bool {
+    cors.allowed_origins
+        .iter()
+        .any(|pattern| origin_matches(pattern, origin))
+}
+
+/// Checks an entry of `cors.allowed_origins` at startup.
+pub fn check_origin_pattern(pattern: &str) -> Result<(), String> {
+    if pattern == "*" {
+        return Ok(());
+    }
+    let url = reqwest::Url::parse(&pattern.replacen("*.", "wildcard.", 1))
+        .map_err(|e| format!("'{}' is not an origin: {}", pattern, e))?;
+    let is_origin = matches!(url.scheme(), "http" | "https")
+        && url.has_host()
+        && url.path() == "/"
+        && !pattern.ends_with('/')
+        && url.query().is_none()
+        && pattern.matches('*').count() <= 1
+        && (!pattern.contains('*') || pattern.contains("://*."));
+    if is_origin {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' is not an origin like https://cr8s.com or https://*.cr8s.com",
+            pattern
+        ))
+    }
+}
+
+/// Checks an entry of `cors.allowed_methods` at startup.
+pub fn check_method(method: &str) -> Result<(), String> {
+    Method::from_str(method)
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not an HTTP method", method))
+}
+
+/// An OPTIONS request, which as a CORS preflight must come from an allowed origin
+/// and ask for allowed methods and headers.
+pub struct Preflight;
+
+impl Preflight {
+    fn check(request: &Request<'_>, cors: &CorsConfig) -> Result<Preflight, String> {
+        let headers = request.headers();
+        let (origin, method) = match (headers.get_one(ORIGIN), headers.get_one(REQUEST_METHOD)) {
+            (Some(origin), Some(method)) => (origin, method),
+            // A plain OPTIONS request rather than a preflight
+            _ => return Ok(Preflight),
+        };
+        if !origin_allowed(cors, origin) {
+            return Err(format!("Origin {} is not allowed", origin));
+        }
+        if !cors
+            .allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
+        {
+            return Err(format!("Method {} is not allowed", method));
+        }
+        let requested_headers = headers
+            .get(REQUEST_HEADERS)
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|header| !header.is_empty());
+        for header in requested_headers {
+            if !cors
+                .allowed_headers
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            {
+                return Err(format!("Header {} is not allowed", header));
+            }
+        }
+        Ok(Preflight)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preflight {
+    type Error = String;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match Preflight::check(request, &config::get().cors) {
+            Ok(preflight) => Outcome::Success(preflight),
+            Err(e) => Outcome::Failure((Status::Forbidden, e)),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Preflight {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let cors = &config::get().cors;
+        Response::build()
+            .status(Status::NoContent)
+            .raw_header(
+                "Access-Control-Allow-Methods",
+                cors.allowed_methods.join(", "),
+            )
+            .raw_header(
+                "Access-Control-Allow-Headers",
+                cors.allowed_headers.join(", "),
+            )
+            .raw_header("Access-Control-Max-Age", cors.max_age_seconds.to_string())
+            .ok()
+    }
+}
+
+/// Answers CORS preflights, the origin headers are added by the `Cors` fairing.
+#[rocket::options("/<_route_args..>")]
+pub fn preflight(
+    _route_args: Option<std::path::PathBuf>,
+    preflight: Result<Preflight, String>,
+) -> Result<Preflight, ApiError> {
+    preflight.map_err(ApiError::Forbidden)
+}
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "Append CORS headers in responses",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut rocket::Response<'r>) {
+        // The allowed origin depends on the request, caches must not share responses
+        res.adjoin_raw_header("Vary", ORIGIN);
+        let cors = &config::get().cors;
+        let origin = match req.headers().get_one(ORIGIN) {
+            Some(origin) if origin_allowed(cors, origin) => origin,
+            _ => return,
+        };
+        // Validation only lets `*` through without credentials, so any origin may read
+        // the response but none with the cookies or authorization of the user
+        if cors.allowed_origins.iter().any(|pattern| pattern == "*") {
+            res.set_raw_header("Access-Control-Allow-Origin", "*");
+        } else {
+            res.set_raw_header("Access-Control-Allow-Origin", origin.to_string());
+        }
+        if cors.allow_credentials {
+            res.set_raw_header("Access-Control-Allow-Credentials", "true");
+        }
+        if !cors.exposed_headers.is_empty() {
+            res.set_raw_header(
+                "Access-Control-Expose-Headers",
+                cors.exposed_headers.join(", "),
+            );
+        }
+    }
+}
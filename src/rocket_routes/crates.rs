@@ -1,3 +1,4 @@
+use diesel::Connection;
 use rocket::{
     http::Status,
     response::status::{Custom, NoContent},
@@ -5,40 +6,53 @@ use rocket::{
 };
 
 use crate::{
-    models::{Crate, NewCrate, User},
+    config,
+    events::{self, Event},
+    models::{Crate, CratePatch, NewCrate, SessionUser},
     repositories::CrateRepository,
-    rocket_routes::{DbConnection, EditorUser},
+    rocket_routes::{
+        Authorized, CrateCreate, CrateDelete, CrateUpdate, DbConnection, Mailer, RequestId,
+    },
 };
 
-use super::server_error;
+use super::preconditions::{IfMatch, IfNoneMatch, Tagged};
+use super::ApiError;
 
-const CRATES_LIMIT: i64 = 100;
+fn crate_not_found(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::NotFound => ApiError::NotFound("Crate not found".to_string()),
+        _ => e.into(),
+    }
+}
 
 #[rocket::get("/crates?<limit>")]
 pub async fn get_crates(
     db: DbConnection,
     limit: Option<i64>,
-    _user: User,
-) -> Result<Value, Custom<Value>> {
+    _user: SessionUser,
+) -> Result<Value, ApiError> {
     db.run(move |connection| {
-        CrateRepository::find_multiple(connection, limit.unwrap_or_else(|| CRATES_LIMIT))
-            .map(|crates| json!(crates))
-            .map_err(|e| server_error(e.into()))
+        CrateRepository::find_multiple(
+            connection,
+            limit.unwrap_or(config::get().page_limits.crates),
+        )
+        .map(|crates| json!(crates))
+        .map_err(ApiError::from)
     })
     .await
 }
 
 #[rocket::get("/crates/<id>")]
-pub async fn view_crate(id: i32, db: DbConnection, _user: User) -> Result<Value, Custom<Value>> {
+pub async fn view_crate(
+    id: i32,
+    db: DbConnection,
+    _user: SessionUser,
+    if_none_match: IfNoneMatch,
+) -> Result<Tagged<Value>, ApiError> {
     db.run(move |connection| {
         CrateRepository::find(connection, id)
-            .map(|a_crate| json!(a_crate))
-            .map_err(|e| match e {
-                diesel::result::Error::NotFound => {
-                    Custom(Status::NotFound, json!("Crate not found"))
-                }
-                _ => server_error(e.into()),
-            })
+            .map(|a_crate| Tagged::conditional(&a_crate, json!(a_crate), &if_none_match))
+            .map_err(crate_not_found)
     })
     .await
 }
@@ -47,12 +61,20 @@ pub async fn view_crate(id: i32, db: DbConnection, _user: User) -> Result<Value,
 pub async fn create_crate(
     new_crate: Json<NewCrate>,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<Custom<Value>, Custom<Value>> {
+    user: Authorized<CrateCreate>,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Custom<Value>>, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        CrateRepository::create(connection, new_crate.into_inner())
-            .map(|a_crate| Custom(Status::Created, json!(a_crate)))
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let a_crate = CrateRepository::create(connection, new_crate.into_inner())?;
+            events::publish(connection, &mailer.0, &Event::created(&actor, &a_crate))?;
+            Ok(Tagged::new(
+                &a_crate,
+                Custom(Status::Created, json!(a_crate)),
+            ))
+        })
     })
     .await
 }
@@ -62,12 +84,55 @@ pub async fn update_crate(
     id: i32,
     a_crate: Json<Crate>,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<Value, Custom<Value>> {
+    user: Authorized<CrateUpdate>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Value>, ApiError> {
+    let actor = user.actor(&request_id);
+    db.run(move |connection| {
+        connection.transaction(|connection| {
+            let current =
+                CrateRepository::find_for_update(connection, id).map_err(crate_not_found)?;
+            if_match.verify(&current)?;
+            let a_crate = CrateRepository::update(connection, id, a_crate.into_inner())
+                .map_err(crate_not_found)?;
+            events::publish(
+                connection,
+                &mailer.0,
+                &Event::updated(&actor, &current, &a_crate),
+            )?;
+            Ok(Tagged::new(&a_crate, json!(a_crate)))
+        })
+    })
+    .await
+}
+
+#[rocket::patch("/crates/<id>", data = "<crate_patch>")]
+pub async fn patch_crate(
+    id: i32,
+    crate_patch: Json<CratePatch>,
+    db: DbConnection,
+    user: Authorized<CrateUpdate>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Value>, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        CrateRepository::update(connection, id, a_crate.into_inner())
-            .map(|a_crate| json!(a_crate))
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let current =
+                CrateRepository::find_for_update(connection, id).map_err(crate_not_found)?;
+            if_match.verify(&current)?;
+            let a_crate = CrateRepository::patch(connection, id, crate_patch.into_inner())
+                .map_err(crate_not_found)?;
+            events::publish(
+                connection,
+                &mailer.0,
+                &Event::updated(&actor, &current, &a_crate),
+            )?;
+            Ok(Tagged::new(&a_crate, json!(a_crate)))
+        })
     })
     .await
 }
@@ -76,12 +141,23 @@ pub async fn update_crate(
 pub async fn delete_crate(
     id: i32,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<NoContent, Custom<Value>> {
+    user: Authorized<CrateDelete>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<NoContent, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        CrateRepository::delete(connection, id)
-            .map(|_| NoContent)
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let current =
+                CrateRepository::find_for_update(connection, id).map_err(crate_not_found)?;
+            if_match.verify(&current)?;
+            if CrateRepository::delete(connection, id)? == 0 {
+                return Err(ApiError::NotFound("Crate not found".to_string()));
+            }
+            events::publish(connection, &mailer.0, &Event::deleted(&actor, &current))?;
+            Ok(NoContent)
+        })
     })
     .await
 }
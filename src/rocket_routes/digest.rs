@@ -0,0 +1,31 @@
+use rocket::response::content::RawHtml;
+
+use crate::{
+    digest,
+    rocket_routes::{Authorized, DbConnection, DigestPreview},
+};
+
+use super::ApiError;
+
+const PREVIEW_HOURS_SINCE: i32 = 24;
+
+/// Renders the digest as it would be sent, without sending anything.
+#[rocket::get("/admin/digest/preview?<hours_since>")]
+pub async fn preview_digest(
+    db: DbConnection,
+    hours_since: Option<i32>,
+    _user: Authorized<DigestPreview>,
+) -> Result<RawHtml<String>, ApiError> {
+    let hours_since = hours_since.unwrap_or(PREVIEW_HOURS_SINCE);
+    if hours_since <= 0 {
+        return Err(ApiError::BadRequest(
+            "hours_since must be positive".to_string(),
+        ));
+    }
+    db.run(move |connection| {
+        digest::render_digest(connection, hours_since)
+            .map(RawHtml)
+            .map_err(|e| ApiError::Internal(format!("Cannot render digest: {}", e)))
+    })
+    .await
+}
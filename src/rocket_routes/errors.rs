@@ -0,0 +1,129 @@
+use log::Level;
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::{serde_json::json, Json};
+use rocket::Request;
+use rocket_db_pools::deadpool_redis::redis::RedisError;
+
+use super::access_log::log_request;
+use super::RequestId;
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    PreconditionFailed(String),
+    UnprocessableEntity(String),
+    PreconditionRequired(String),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::PreconditionFailed(_) => Status::PreconditionFailed,
+            ApiError::UnprocessableEntity(_) => Status::UnprocessableEntity,
+            ApiError::PreconditionRequired(_) => Status::PreconditionRequired,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            ApiError::BadRequest(detail)
+            | ApiError::Unauthorized(detail)
+            | ApiError::Forbidden(detail)
+            | ApiError::NotFound(detail)
+            | ApiError::PreconditionFailed(detail)
+            | ApiError::UnprocessableEntity(detail)
+            | ApiError::PreconditionRequired(detail) => detail,
+            // Internal details are logged, never sent to the client
+            ApiError::Internal(_) => "The server encountered an internal error",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Internal(e) => write!(f, "{}", e),
+            _ => write!(f, "{}", self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => ApiError::NotFound("Resource not found".to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<RedisError> for ApiError {
+    fn from(e: RedisError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        let request_id = RequestId::from_request_cache(request);
+        if let ApiError::Internal(e) = &self {
+            log_request(Level::Error, request_id, e);
+        }
+
+        let body = json!({
+            "type": "about:blank",
+            "title": status.reason_lossy(),
+            "status": status.code,
+            "detail": self.detail(),
+            "request_id": request_id.to_string(),
+        });
+
+        Response::build_from(Json(body).respond_to(request)?)
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
+            .ok()
+    }
+}
+
+#[rocket::catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthorized("Missing or invalid authentication token".to_string())
+}
+
+#[rocket::catch(403)]
+pub fn forbidden() -> ApiError {
+    ApiError::Forbidden("Insufficient permissions for this resource".to_string())
+}
+
+#[rocket::catch(404)]
+pub fn not_found() -> ApiError {
+    ApiError::NotFound("Resource not found".to_string())
+}
+
+#[rocket::catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::UnprocessableEntity("The request body could not be parsed".to_string())
+}
+
+#[rocket::catch(428)]
+pub fn precondition_required() -> ApiError {
+    ApiError::PreconditionRequired("The If-Match header is required".to_string())
+}
+
+#[rocket::catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::Internal("Unhandled internal server error".to_string())
+}
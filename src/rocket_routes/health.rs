@@ -0,0 +1,106 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use log::Level;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::serde::json::{json, Value};
+use rocket::tokio::time::timeout;
+use rocket::{Orbit, Rocket};
+use rocket_db_pools::deadpool_redis::redis;
+use rocket_db_pools::Database;
+
+use crate::repositories::DatabaseRepository;
+use crate::rocket_routes::access_log::log_request;
+use crate::rocket_routes::{CacheConnection, DbConnection, Pools, RequestId};
+
+use super::ApiError;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs a dependency check within `CHECK_TIMEOUT`, reporting its status and latency.
+async fn check<F>(request_id: &RequestId, dependency: &str, check: F) -> (bool, Value)
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let result = match timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {:?}", CHECK_TIMEOUT)),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(()) => (true, json!({ "status": "up", "latency_ms": latency_ms })),
+        Err(e) => {
+            log_request(
+                Level::Warn,
+                request_id,
+                format!("Readiness check of {} failed: {}", dependency, e),
+            );
+            (
+                false,
+                json!({ "status": "down", "latency_ms": latency_ms, "error": e }),
+            )
+        }
+    }
+}
+
+async fn check_postgres(rocket: &Rocket<Orbit>) -> Result<(), String> {
+    let db = DbConnection::get_one(rocket)
+        .await
+        .ok_or("No connection available")?;
+    db.run(DatabaseRepository::ping)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn check_redis(rocket: &Rocket<Orbit>) -> Result<(), String> {
+    let pool = CacheConnection::fetch(rocket).ok_or("Pool is not initialized")?;
+    let mut connection = pool.get().await.map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Liveness, the process answers requests.
+#[rocket::get("/health")]
+pub fn health() -> Value {
+    json!({ "status": "up" })
+}
+
+/// Readiness, every dependency answers; 503 otherwise.
+#[rocket::get("/ready")]
+pub async fn ready(pools: Pools<'_>, request_id: RequestId) -> Custom<Value> {
+    let rocket = pools.0;
+    let ((postgres_up, postgres), (redis_up, redis)) = rocket::tokio::join!(
+        check(&request_id, "postgres", check_postgres(rocket)),
+        check(&request_id, "redis", check_redis(rocket))
+    );
+    let (status, body_status) = if postgres_up && redis_up {
+        (Status::Ok, "up")
+    } else {
+        (Status::ServiceUnavailable, "down")
+    };
+    Custom(
+        status,
+        json!({
+            "status": body_status,
+            "checks": { "postgres": postgres, "redis": redis },
+        }),
+    )
+}
+
+#[rocket::get("/version")]
+pub async fn version(db: DbConnection) -> Result<Value, ApiError> {
+    db.run(|connection| {
+        let migration = DatabaseRepository::migration_version(connection)?;
+        Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "commit": env!("GIT_COMMIT"),
+            "migration": migration,
+        }))
+    })
+    .await
+}
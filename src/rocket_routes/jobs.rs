@@ -0,0 +1,25 @@
+use rocket::serde::json::{json, Value};
+use rocket::State;
+
+use crate::{
+    repositories::JobRunRepository,
+    rocket_routes::{Authorized, DbConnection, JobRead},
+    scheduler::Jobs,
+};
+
+use super::ApiError;
+
+#[rocket::get("/admin/jobs")]
+pub async fn get_jobs(
+    db: DbConnection,
+    jobs: &State<Jobs>,
+    _user: Authorized<JobRead>,
+) -> Result<Value, ApiError> {
+    let jobs = jobs.inner().clone();
+    db.run(move |connection| {
+        JobRunRepository::find_all(connection)
+            .map(|job_runs| json!(jobs.status(job_runs)))
+            .map_err(ApiError::from)
+    })
+    .await
+}
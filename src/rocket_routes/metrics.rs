@@ -0,0 +1,98 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::{Build, Data, Request, Response, Rocket};
+use rocket_db_pools::Database;
+
+use crate::metrics::{self, PoolGauges, UNMATCHED_ROUTE};
+use crate::models::ConnectionCounts;
+use crate::repositories::DatabaseRepository;
+use crate::rocket_routes::{CacheConnection, DbConnection, Pools, RequestStart};
+
+const POSTGRES: &str = "postgres";
+
+/// Size of the Postgres pool as configured, its connections are not exposed.
+struct PostgresPoolSize(u32);
+
+pub struct Metrics;
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Record request metrics",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        match rocket_sync_db_pools::Config::from(POSTGRES, &rocket) {
+            Ok(config) => Ok(rocket.manage(PostgresPoolSize(config.pool_size))),
+            // The database fairing reports the invalid configuration
+            Err(_) => Ok(rocket),
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        RequestStart::from_request_cache(req);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let started = RequestStart::from_request_cache(req);
+        let route = req
+            .route()
+            .map(|route| route.uri.path())
+            .unwrap_or(UNMATCHED_ROUTE);
+        metrics::record_request(
+            req.method().as_str(),
+            route,
+            res.status().code,
+            started.0.elapsed(),
+        );
+    }
+}
+
+fn postgres_gauges(pools: &Pools<'_>) -> Option<PoolGauges> {
+    let size = pools.0.state::<PostgresPoolSize>()?;
+    Some(PoolGauges {
+        pool: POSTGRES,
+        connections: None,
+        max: size.0.into(),
+    })
+}
+
+/// Connections of every client of the database, none when it cannot be reached so that
+/// the other metrics are still scraped.
+async fn database_connections(pools: &Pools<'_>) -> Option<ConnectionCounts> {
+    let db = DbConnection::get_one(pools.0).await?;
+    db.run(DatabaseRepository::connection_counts)
+        .await
+        .map_err(|e| log::warn!("Cannot count the database connections: {}", e))
+        .ok()
+}
+
+fn redis_gauges(pools: &Pools<'_>) -> Option<PoolGauges> {
+    let status = CacheConnection::fetch(pools.0)?.status();
+    let idle = status.available.max(0) as i64;
+    Some(PoolGauges {
+        pool: "redis",
+        connections: Some(ConnectionCounts {
+            active: status.size as i64 - idle,
+            idle,
+        }),
+        max: status.max_size as i64,
+    })
+}
+
+/// Metrics in the Prometheus text format, for scraping.
+#[rocket::get("/metrics")]
+pub async fn get_metrics(pools: Pools<'_>) -> (ContentType, String) {
+    let gauges: Vec<_> = postgres_gauges(&pools)
+        .into_iter()
+        .chain(redis_gauges(&pools))
+        .collect();
+    let database = database_connections(&pools).await;
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics::render(&gauges, database.as_ref()),
+    )
+}
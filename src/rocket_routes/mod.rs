@@ -1,23 +1,41 @@
+pub mod access_log;
+pub mod audit;
 pub mod authorization;
+pub mod cors;
 pub mod crates;
+pub mod digest;
+pub mod errors;
+pub mod health;
+pub mod jobs;
+pub mod metrics;
+pub mod notifications;
+pub mod permissions;
+pub mod preconditions;
+pub mod roles;
 pub mod rustaceans;
+pub mod subscriptions;
+pub mod webhooks;
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Instant;
 
 use diesel::PgConnection;
-use lettre::transport::smtp::authentication::Credentials;
-use rocket::fairing::{Fairing, Info, Kind};
+use log::Level;
 use rocket::http::hyper::header;
 use rocket::http::Status;
+use rocket::outcome::try_outcome;
 use rocket::request::{FromRequest, Outcome};
-use rocket::response::status::Custom;
-use rocket::serde::json::{serde_json::json, Value};
-use rocket::Request;
+use rocket::response::content::RawHtml;
+use rocket::{Orbit, Request, Rocket};
 
-use rocket_db_pools::deadpool_redis::redis::AsyncCommands;
+use rand::{distributions::Alphanumeric, Rng};
 use rocket_db_pools::{deadpool_redis, Connection, Database};
+use tera::Context;
 
 use crate::mail::HtmlMailer;
-use crate::models::{RoleCode, User};
-use crate::repositories::{RoleRepository, UserRepository};
+use crate::models::{Actor, SessionUser};
+use crate::repositories::{PermissionRepository, SessionRepository};
 
 #[rocket_sync_db_pools::database("postgres")]
 pub struct DbConnection(PgConnection);
@@ -26,128 +44,317 @@ pub struct DbConnection(PgConnection);
 #[database("redis")]
 pub struct CacheConnection(deadpool_redis::Pool);
 
-pub fn server_error(e: Box<dyn std::error::Error>) -> Custom<Value> {
-    log::error!("{}", e);
-    Custom(Status::InternalServerError, json!("Error"))
+use access_log::log_request;
+pub use errors::ApiError;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const REQUEST_ID_LENGTH: usize = 32;
+/// Width of `audit_events.request_id`, longer ids would fail the audited mutations.
+const REQUEST_ID_MAX_LENGTH: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Whether an incoming id is safe to echo and log, e.g. a UUID set by a proxy.
+    fn is_valid(id: &str) -> bool {
+        (1..=REQUEST_ID_MAX_LENGTH).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+
+    /// Returns the id of the current request, the `X-Request-Id` of the client when valid,
+    /// otherwise generated on first access.
+    pub fn from_request_cache<'r>(request: &'r Request<'_>) -> &'r RequestId {
+        request.local_cache(|| {
+            if let Some(id) = request
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .filter(|id| RequestId::is_valid(id))
+            {
+                return RequestId(id.to_string());
+            }
+            let id = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(REQUEST_ID_LENGTH)
+                .map(char::from)
+                .collect();
+            RequestId(id)
+        })
+    }
 }
 
-pub struct EditorUser(User);
+/// When the request arrived.
+pub struct RequestStart(pub Instant);
+
+impl RequestStart {
+    pub fn from_request_cache<'r>(request: &'r Request<'_>) -> &'r RequestStart {
+        request.local_cache(|| RequestStart(Instant::now()))
+    }
+}
+
+/// The user the request authenticated as, if any.
+pub struct AuthenticatedUserId(pub Option<i32>);
+
+impl AuthenticatedUserId {
+    pub fn from_request_cache<'r>(request: &'r Request<'_>) -> &'r AuthenticatedUserId {
+        request.local_cache(|| AuthenticatedUserId(None))
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for EditorUser {
+impl<'r> FromRequest<'r> for RequestId {
     type Error = ();
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let user = request
-            .guard::<User>()
-            .await
-            .expect("Cannot retrieve logged in user in request guard");
-        let db = request
-            .guard::<DbConnection>()
-            .await
-            .expect("Cannot connect to postgres in request guard");
-
-        let editor_result = db
-            .run(
-                |connection| match RoleRepository::find_by_user(connection, &user) {
-                    Ok(roles) => {
-                        log::info!("Assigned roles {:?}", roles);
-                        let is_editor = roles.iter().any(|role| match role.code {
-                            RoleCode::Admin => true,
-                            RoleCode::Editor => true,
-                            _ => false,
-                        });
-                        log::info!("Is editor is {:?}", is_editor);
-                        is_editor.then_some(EditorUser(user))
-                    }
-                    _ => None,
-                },
-            )
-            .await;
-        match editor_result {
-            Some(editor) => Outcome::Success(editor),
-            _ => Outcome::Failure((Status::Unauthorized, ())),
-        }
+        Outcome::Success(RequestId::from_request_cache(request).clone())
     }
 }
 
+/// The running instance, to reach the pools without waiting on their guards.
+pub struct Pools<'r>(pub &'r Rocket<Orbit>);
+
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for User {
+impl<'r> FromRequest<'r> for Pools<'r> {
     type Error = ();
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let auth_header = request
-            .headers()
-            .get_one(header::AUTHORIZATION.as_str())
-            .map(|v| v.split_whitespace().collect::<Vec<_>>())
-            .filter(|v| v.len() == 2 && v[0] == "Bearer");
-
-        if let Some(header_value) = auth_header {
-            let mut cache = request
-                .guard::<Connection<CacheConnection>>()
-                .await
-                .expect("Cannot connect to redis in request guard");
-            let db = request
-                .guard::<DbConnection>()
-                .await
-                .expect("Cannot connect to postgres in request guard");
-            let result = cache
-                .get::<_, i32>(format!("sessions/{}", header_value[1]))
-                .await;
-            if let Ok(user_id) = result {
-                return match db.run(move |c| UserRepository::find(c, user_id)).await {
-                    Ok(user) => Outcome::Success(user),
-                    _ => Outcome::Failure((Status::Unauthorized, ())),
-                };
-            }
+        Outcome::Success(Pools(request.rocket()))
+    }
+}
+
+/// A permission a route requires of the logged in user, see `Authorized`.
+pub trait RequiredPermission: Send + Sync + 'static {
+    const CODE: &'static str;
+}
+
+pub struct CrateCreate;
+impl RequiredPermission for CrateCreate {
+    const CODE: &'static str = "crate:create";
+}
+
+pub struct CrateUpdate;
+impl RequiredPermission for CrateUpdate {
+    const CODE: &'static str = "crate:update";
+}
+
+pub struct CrateDelete;
+impl RequiredPermission for CrateDelete {
+    const CODE: &'static str = "crate:delete";
+}
+
+pub struct RustaceanCreate;
+impl RequiredPermission for RustaceanCreate {
+    const CODE: &'static str = "rustacean:create";
+}
+
+pub struct RustaceanUpdate;
+impl RequiredPermission for RustaceanUpdate {
+    const CODE: &'static str = "rustacean:update";
+}
+
+pub struct RustaceanDelete;
+impl RequiredPermission for RustaceanDelete {
+    const CODE: &'static str = "rustacean:delete";
+}
+
+pub struct AuditRead;
+impl RequiredPermission for AuditRead {
+    const CODE: &'static str = "audit:read";
+}
+
+pub struct DigestPreview;
+impl RequiredPermission for DigestPreview {
+    const CODE: &'static str = "digest:preview";
+}
+
+pub struct JobRead;
+impl RequiredPermission for JobRead {
+    const CODE: &'static str = "job:read";
+}
+
+pub struct WebhookManage;
+impl RequiredPermission for WebhookManage {
+    const CODE: &'static str = "webhook:manage";
+}
+
+pub struct UserManage;
+impl RequiredPermission for UserManage {
+    const CODE: &'static str = "user:manage";
+}
+
+/// The logged in user, granted the permission `P` by one of their roles.
+pub struct Authorized<P: RequiredPermission>(pub SessionUser, PhantomData<P>);
+
+impl<P: RequiredPermission> Authorized<P> {
+    pub fn actor(&self, request_id: &RequestId) -> Actor {
+        Actor {
+            user_id: Some(self.0.id),
+            request_id: Some(request_id.to_string()),
         }
+    }
+}
 
-        Outcome::Failure((Status::Unauthorized, ()))
+/// The token of the `Authorization: Bearer <token>` header.
+fn session_id<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    let mut parts = request
+        .headers()
+        .get_one(header::AUTHORIZATION.as_str())?
+        .split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("Bearer"), Some(session_id), None) => Some(session_id),
+        _ => None,
     }
 }
 
+/// Permission codes of the user's roles, cached for the session until roles are edited.
+async fn session_permissions(
+    request: &Request<'_>,
+    session_id: &str,
+    user: &SessionUser,
+) -> Result<Vec<String>, String> {
+    let mut cache = request
+        .guard::<Connection<CacheConnection>>()
+        .await
+        .succeeded()
+        .ok_or("No cache connection available")?;
+    let generation = SessionRepository::permissions_generation(&mut cache)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cached = SessionRepository::find_permissions(session_id, generation, &mut cache)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(permissions) = cached {
+        return Ok(permissions);
+    }
+
+    let db = request
+        .guard::<DbConnection>()
+        .await
+        .succeeded()
+        .ok_or("No database connection available")?;
+    let roles = user.roles.clone();
+    let permissions = db
+        .run(move |connection| PermissionRepository::find_codes_by_roles(connection, &roles))
+        .await
+        .map_err(|e| e.to_string())?;
+    SessionRepository::cache_permissions(session_id, generation, permissions.clone(), &mut cache)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(permissions)
+}
+
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for HtmlMailer {
+impl<'r, P: RequiredPermission> FromRequest<'r> for Authorized<P> {
     type Error = ();
-
-    async fn from_request(_request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        if let Ok(tera) = tera::Tera::new("templates/**/*.html") {
-            let smtp_host = std::env::var("SMTP_HOST").expect("Cannot load SMTP host from env");
-            let smtp_username =
-                std::env::var("SMTP_USERNAME").expect("Cannot load SMTP username from env");
-            let smtp_password =
-                std::env::var("SMTP_PASSWORD").expect("Cannot load SMTP password from env");
-
-            let credentials = Credentials::new(smtp_username, smtp_password);
-            let mailer = HtmlMailer {
-                smtp_host,
-                credentials,
-                template_engine: tera,
-            };
-            return Outcome::Success(mailer);
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(request.guard::<SessionUser>().await);
+        let Some(session_id) = session_id(request) else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+        let request_id = RequestId::from_request_cache(request);
+        match session_permissions(request, session_id, &user).await {
+            Ok(permissions) if permissions.iter().any(|code| code == P::CODE) => {
+                Outcome::Success(Authorized(user, PhantomData))
+            }
+            Ok(_) => {
+                log_request(
+                    Level::Info,
+                    request_id,
+                    format!("User {} lacks the {} permission", user.id, P::CODE),
+                );
+                Outcome::Failure((Status::Forbidden, ()))
+            }
+            Err(e) => {
+                log_request(
+                    Level::Error,
+                    request_id,
+                    format!("Cannot load permissions of the logged in user: {}", e),
+                );
+                Outcome::Failure((Status::InternalServerError, ()))
+            }
         }
-        Outcome::Failure((Status::InternalServerError, ()))
     }
 }
 
-#[rocket::options("/<_route_args..>")]
-pub fn options(_route_args: Option<std::path::PathBuf>) {
-    // Just to add CORS header via the fairing
+async fn find_session(request: &Request<'_>) -> Option<SessionUser> {
+    let session_id = session_id(request)?;
+    let mut cache = request
+        .guard::<Connection<CacheConnection>>()
+        .await
+        .succeeded()?;
+    SessionRepository::find_session(session_id, &mut cache)
+        .await
+        .unwrap_or_else(|e| {
+            log_request(
+                Level::Error,
+                RequestId::from_request_cache(request),
+                format!("Cannot load session: {}", e),
+            );
+            None
+        })
 }
 
-pub struct Cors;
+/// The session of the request, resolved once however many guards need it.
+struct CachedSessionUser(Option<SessionUser>);
 
 #[rocket::async_trait]
-impl Fairing for Cors {
-    fn info(&self) -> Info {
-        Info {
-            name: "Append CORS headers in responses",
-            kind: Kind::Response,
+impl<'r> FromRequest<'r> for SessionUser {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let cached = request
+            .local_cache_async(async { CachedSessionUser(find_session(request).await) })
+            .await;
+        match &cached.0 {
+            Some(user) => {
+                request.local_cache(|| AuthenticatedUserId(Some(user.id)));
+                Outcome::Success(user.clone())
+            }
+            None => Outcome::Failure((Status::Unauthorized, ())),
         }
     }
+}
+
+/// The mailer built once at startup and managed as state, so the templates are not
+/// parsed again on every request.
+#[derive(Clone)]
+pub struct Mailer(pub Arc<HtmlMailer>);
 
-    async fn on_response<'r>(&self, _req: &'r Request<'_>, res: &mut rocket::Response<'r>) {
-        res.set_raw_header("Access-Control-Allow-Origin", "*");
-        res.set_raw_header("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE");
-        res.set_raw_header("Access-Control-Allow-Headers", "*");
-        res.set_raw_header("Access-Control-Allow-Credentials", "true");
+impl Mailer {
+    /// Renders one of the `pages/` templates shown to users following links of emails.
+    pub fn render_page(
+        &self,
+        template_name: &str,
+        context: &Context,
+    ) -> Result<RawHtml<String>, ApiError> {
+        self.0
+            .template_engine
+            .render(template_name, context)
+            .map(RawHtml)
+            .map_err(|e| ApiError::Internal(format!("Cannot render {}: {}", template_name, e)))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Mailer {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.rocket().state::<Mailer>() {
+            Some(mailer) => Outcome::Success(mailer.clone()),
+            None => {
+                log_request(
+                    Level::Error,
+                    RequestId::from_request_cache(request),
+                    "No mailer is managed",
+                );
+                Outcome::Failure((Status::InternalServerError, ()))
+            }
+        }
     }
 }
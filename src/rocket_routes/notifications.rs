@@ -0,0 +1,104 @@
+use diesel::Connection;
+use rocket::response::content::RawHtml;
+use rocket::serde::json::{json, Json, Value};
+use tera::Context;
+
+use crate::{
+    auth::{self, TokenError},
+    models::{NotificationPreferences, SessionUser},
+    notifications,
+    repositories::NotificationPreferencesRepository,
+    rocket_routes::{DbConnection, Mailer},
+};
+
+use super::ApiError;
+
+const VERIFY_EMAIL_PAGE: &str = "pages/verify_email.html";
+
+fn verification_error(e: TokenError) -> ApiError {
+    match e {
+        TokenError::Invalid => ApiError::BadRequest("Invalid verification token".to_string()),
+        TokenError::Expired => ApiError::BadRequest("Expired verification token".to_string()),
+        TokenError::MissingSecret => ApiError::Internal(e.to_string()),
+    }
+}
+
+/// Preferences of the user, `email` is null until they are saved once.
+#[rocket::get("/me/notifications")]
+pub async fn get_notifications(db: DbConnection, user: SessionUser) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        let preferences = NotificationPreferencesRepository::find_by_user(connection, user.id)?;
+        Ok(match preferences {
+            Some(preferences) => json!(preferences),
+            None => json!({
+                "email": null,
+                "crate_updated": true,
+                "crate_deleted": true,
+                "email_verified_at": null,
+            }),
+        })
+    })
+    .await
+}
+
+/// Saves the preferences, a new email stays unverified until the user follows the
+/// link mailed to it.
+#[rocket::put("/me/notifications", format = "json", data = "<preferences>")]
+pub async fn update_notifications(
+    preferences: Json<NotificationPreferences>,
+    db: DbConnection,
+    user: SessionUser,
+    mailer: Mailer,
+) -> Result<Value, ApiError> {
+    let mut preferences = preferences.into_inner();
+    preferences.email = preferences.email.trim().to_string();
+    preferences.email.parse::<lettre::Address>().map_err(|_| {
+        ApiError::UnprocessableEntity(format!("Invalid email address '{}'", preferences.email))
+    })?;
+    preferences.user_id = user.id;
+    db.run(move |connection| {
+        connection.transaction(|connection| {
+            let current = NotificationPreferencesRepository::find_by_user(connection, user.id)?
+                .filter(|current| current.email.eq_ignore_ascii_case(&preferences.email));
+            preferences.email_verified_at = current
+                .as_ref()
+                .and_then(|current| current.email_verified_at);
+            let preferences = NotificationPreferencesRepository::save(connection, &preferences)?;
+            if current.is_none() {
+                notifications::queue_email_verification(connection, &mailer.0, &user, &preferences)
+                    .map_err(|e| {
+                        ApiError::Internal(format!("Cannot queue the verification email: {}", e))
+                    })?;
+            }
+            Ok(json!(preferences))
+        })
+    })
+    .await
+}
+
+/// Target of the link in the verification email, no login required. Asks for a
+/// confirmation so that link scanners following it do not verify anything.
+#[rocket::get("/notifications/verify?<token>")]
+pub async fn confirm_email_verification(
+    token: String,
+    mailer: Mailer,
+) -> Result<RawHtml<String>, ApiError> {
+    auth::email_verification_user_id(&token).map_err(verification_error)?;
+    let mut context = Context::new();
+    context.insert("token", &token);
+    mailer.render_page(VERIFY_EMAIL_PAGE, &context)
+}
+
+#[rocket::post("/notifications/verify?<token>")]
+pub async fn verify_email(token: String, db: DbConnection) -> Result<Value, ApiError> {
+    let user_id = auth::email_verification_user_id(&token).map_err(verification_error)?;
+    db.run(move |connection| {
+        let preferences = NotificationPreferencesRepository::find_by_user(connection, user_id)?
+            .ok_or_else(|| verification_error(TokenError::Invalid))?;
+        auth::verify_email_verification_token(&token, &preferences.email)
+            .map_err(verification_error)?;
+        NotificationPreferencesRepository::mark_verified(connection, user_id)?;
+        Ok(json!({ "verified": true }))
+    })
+    .await
+}
@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+use diesel::Connection as _;
+use rocket::serde::json::{json, Json, Value};
+use rocket_db_pools::Connection;
+
+use crate::{
+    models::{Role, RoleCode, RolePermissions},
+    repositories::{AuditRepository, PermissionRepository, RoleRepository, SessionRepository},
+    rocket_routes::{
+        Authorized, CacheConnection, DbConnection, RequestId, RequiredPermission, UserManage,
+    },
+};
+
+use super::ApiError;
+
+fn find_role(connection: &mut diesel::PgConnection, code: &str) -> Result<Role, ApiError> {
+    let code = RoleCode::from_str(code).map_err(ApiError::NotFound)?;
+    RoleRepository::find_by_code(connection, &code).map_err(|e| match e {
+        diesel::result::Error::NotFound => {
+            ApiError::NotFound(format!("Role '{}' does not exist", code))
+        }
+        _ => e.into(),
+    })
+}
+
+fn role_permissions(
+    connection: &mut diesel::PgConnection,
+    role: &Role,
+) -> Result<RolePermissions, ApiError> {
+    let permissions = PermissionRepository::find_by_role(connection, role)?;
+    Ok(RolePermissions {
+        role_id: role.id,
+        role: role.code,
+        permissions: permissions
+            .into_iter()
+            .map(|permission| permission.code)
+            .collect(),
+    })
+}
+
+#[rocket::get("/permissions")]
+pub async fn get_permissions(
+    db: DbConnection,
+    _user: Authorized<UserManage>,
+) -> Result<Value, ApiError> {
+    db.run(|connection| {
+        PermissionRepository::find_all(connection)
+            .map(|permissions| json!(permissions))
+            .map_err(ApiError::from)
+    })
+    .await
+}
+
+#[rocket::get("/roles/<code>/permissions")]
+pub async fn get_role_permissions(
+    code: String,
+    db: DbConnection,
+    _user: Authorized<UserManage>,
+) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        let role = find_role(connection, &code)?;
+        role_permissions(connection, &role).map(|role_permissions| json!(role_permissions))
+    })
+    .await
+}
+
+/// Grants exactly the listed permissions to the role, effective on the next request of
+/// every session.
+#[rocket::put("/roles/<code>/permissions", format = "json", data = "<codes>")]
+pub async fn update_role_permissions(
+    code: String,
+    codes: Json<Vec<String>>,
+    db: DbConnection,
+    mut cache: Connection<CacheConnection>,
+    user: Authorized<UserManage>,
+    request_id: RequestId,
+) -> Result<Value, ApiError> {
+    let actor = user.actor(&request_id);
+    let mut codes = codes.into_inner();
+    codes.sort();
+    codes.dedup();
+    let updated = db
+        .run(move |connection| {
+            connection.transaction(|connection| {
+                let role = find_role(connection, &code)?;
+                if role.code == RoleCode::Admin && !codes.iter().any(|c| c == UserManage::CODE) {
+                    return Err(ApiError::UnprocessableEntity(format!(
+                        "The admin role cannot lose the {} permission",
+                        UserManage::CODE
+                    )));
+                }
+                let permissions = PermissionRepository::find_by_codes(connection, &codes)?;
+                if let Some(unknown) = codes
+                    .iter()
+                    .find(|c| !permissions.iter().any(|permission| &permission.code == *c))
+                {
+                    return Err(ApiError::UnprocessableEntity(format!(
+                        "Unknown permission '{}'",
+                        unknown
+                    )));
+                }
+
+                let before = role_permissions(connection, &role)?;
+                PermissionRepository::replace_for_role(connection, &role, &permissions)?;
+                let after = role_permissions(connection, &role)?;
+                AuditRepository::record(connection, &actor, "update", Some(&before), Some(&after))?;
+                Ok(after)
+            })
+        })
+        .await?;
+    SessionRepository::invalidate_permissions(&mut cache).await?;
+    Ok(json!(updated))
+}
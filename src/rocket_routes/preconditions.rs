@@ -0,0 +1,122 @@
+use chrono::NaiveDateTime;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+use crate::models::{Crate, Rustacean};
+
+use super::ApiError;
+
+/// Entities whose `updated_at` column doubles as their version for ETags.
+pub trait Versioned {
+    fn updated_at(&self) -> NaiveDateTime;
+
+    fn etag(&self) -> String {
+        format!("\"{}\"", self.updated_at().and_utc().timestamp_micros())
+    }
+}
+
+impl Versioned for Crate {
+    fn updated_at(&self) -> NaiveDateTime {
+        self.updated_at
+    }
+}
+
+impl Versioned for Rustacean {
+    fn updated_at(&self) -> NaiveDateTime {
+        self.updated_at
+    }
+}
+
+fn list_contains(header: &str, etag: &str, weak: bool) -> bool {
+    header.split(',').map(str::trim).any(|tag| {
+        let tag = if weak {
+            tag.trim_start_matches("W/")
+        } else {
+            tag
+        };
+        tag == "*" || tag == etag
+    })
+}
+
+/// Mandatory `If-Match` header for mutating requests, missing it yields a 428.
+pub struct IfMatch(String);
+
+impl IfMatch {
+    pub fn verify<T: Versioned>(&self, current: &T) -> Result<(), ApiError> {
+        if list_contains(&self.0, &current.etag(), false) {
+            Ok(())
+        } else {
+            Err(ApiError::PreconditionFailed(
+                "The resource was modified since it was last fetched".to_string(),
+            ))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("If-Match") {
+            Some(value) => Outcome::Success(IfMatch(value.to_string())),
+            None => Outcome::Failure((Status::PreconditionRequired, ())),
+        }
+    }
+}
+
+/// Optional `If-None-Match` header for conditional GETs.
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch {
+    pub fn matches<T: Versioned>(&self, current: &T) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|header| list_contains(header, &current.etag(), true))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = ();
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IfNoneMatch(
+            request.headers().get_one("If-None-Match").map(String::from),
+        ))
+    }
+}
+
+/// Wraps a response with the `ETag` of the entity it represents.
+pub enum Tagged<R> {
+    Fresh(String, R),
+    NotModified(String),
+}
+
+impl<R> Tagged<R> {
+    pub fn new<T: Versioned>(entity: &T, response: R) -> Self {
+        Tagged::Fresh(entity.etag(), response)
+    }
+
+    pub fn conditional<T: Versioned>(entity: &T, response: R, if_none_match: &IfNoneMatch) -> Self {
+        if if_none_match.matches(entity) {
+            Tagged::NotModified(entity.etag())
+        } else {
+            Tagged::new(entity, response)
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Tagged::Fresh(etag, response) => Response::build_from(response.respond_to(request)?)
+                .raw_header("ETag", etag)
+                .ok(),
+            Tagged::NotModified(etag) => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .ok(),
+        }
+    }
+}
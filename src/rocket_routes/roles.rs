@@ -0,0 +1,18 @@
+use rocket::serde::json::{json, Value};
+
+use crate::{
+    repositories::RoleRepository,
+    rocket_routes::{Authorized, DbConnection, UserManage},
+};
+
+use super::ApiError;
+
+#[rocket::get("/roles")]
+pub async fn get_roles(db: DbConnection, _user: Authorized<UserManage>) -> Result<Value, ApiError> {
+    db.run(|connection| {
+        RoleRepository::find_all(connection)
+            .map(|roles| json!(roles))
+            .map_err(ApiError::from)
+    })
+    .await
+}
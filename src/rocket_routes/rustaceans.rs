@@ -1,3 +1,4 @@
+use diesel::Connection;
 use rocket::{
     http::Status,
     response::status::{Custom, NoContent},
@@ -5,25 +6,39 @@ use rocket::{
 };
 
 use crate::{
-    models::{NewRustacean, Rustacean, User},
+    config,
+    events::{self, Event},
+    models::{NewRustacean, Rustacean, RustaceanPatch, SessionUser},
     repositories::RustaceanRepository,
-    rocket_routes::{DbConnection, EditorUser},
+    rocket_routes::{
+        Authorized, DbConnection, Mailer, RequestId, RustaceanCreate, RustaceanDelete,
+        RustaceanUpdate,
+    },
 };
 
-use super::server_error;
+use super::preconditions::{IfMatch, IfNoneMatch, Tagged};
+use super::ApiError;
 
-const RUSTACEANS_LIMIT: i64 = 100;
+fn rustacean_not_found(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::NotFound => ApiError::NotFound("Rustacean not found".to_string()),
+        _ => e.into(),
+    }
+}
 
 #[rocket::get("/rustaceans?<limit>")]
 pub async fn get_rustaceans(
     db: DbConnection,
     limit: Option<i64>,
-    _user: User,
-) -> Result<Value, Custom<Value>> {
+    _user: SessionUser,
+) -> Result<Value, ApiError> {
     db.run(move |connection| {
-        RustaceanRepository::find_multiple(connection, limit.unwrap_or_else(|| RUSTACEANS_LIMIT))
-            .map(|rustaceans| json!(rustaceans))
-            .map_err(|e| server_error(e.into()))
+        RustaceanRepository::find_multiple(
+            connection,
+            limit.unwrap_or(config::get().page_limits.rustaceans),
+        )
+        .map(|rustaceans| json!(rustaceans))
+        .map_err(ApiError::from)
     })
     .await
 }
@@ -32,17 +47,13 @@ pub async fn get_rustaceans(
 pub async fn view_rustacean(
     id: i32,
     db: DbConnection,
-    _user: User,
-) -> Result<Value, Custom<Value>> {
+    _user: SessionUser,
+    if_none_match: IfNoneMatch,
+) -> Result<Tagged<Value>, ApiError> {
     db.run(move |connection| {
         RustaceanRepository::find(connection, id)
-            .map(|rustacean| json!(rustacean))
-            .map_err(|e| match e {
-                diesel::result::Error::NotFound => {
-                    Custom(Status::NotFound, json!("Rustacean not found"))
-                }
-                _ => server_error(e.into()),
-            })
+            .map(|rustacean| Tagged::conditional(&rustacean, json!(rustacean), &if_none_match))
+            .map_err(rustacean_not_found)
     })
     .await
 }
@@ -51,12 +62,20 @@ pub async fn view_rustacean(
 pub async fn create_rustacean(
     new_rustacean: Json<NewRustacean>,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<Custom<Value>, Custom<Value>> {
+    user: Authorized<RustaceanCreate>,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Custom<Value>>, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        RustaceanRepository::create(connection, new_rustacean.into_inner())
-            .map(|rustacean| Custom(Status::Created, json!(rustacean)))
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let rustacean = RustaceanRepository::create(connection, new_rustacean.into_inner())?;
+            events::publish(connection, &mailer.0, &Event::created(&actor, &rustacean))?;
+            Ok(Tagged::new(
+                &rustacean,
+                Custom(Status::Created, json!(rustacean)),
+            ))
+        })
     })
     .await
 }
@@ -66,12 +85,56 @@ pub async fn update_rustacean(
     id: i32,
     rustacean: Json<Rustacean>,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<Value, Custom<Value>> {
+    user: Authorized<RustaceanUpdate>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Value>, ApiError> {
+    let actor = user.actor(&request_id);
+    db.run(move |connection| {
+        connection.transaction(|connection| {
+            let current = RustaceanRepository::find_for_update(connection, id)
+                .map_err(rustacean_not_found)?;
+            if_match.verify(&current)?;
+            let rustacean = RustaceanRepository::update(connection, id, rustacean.into_inner())
+                .map_err(rustacean_not_found)?;
+            events::publish(
+                connection,
+                &mailer.0,
+                &Event::updated(&actor, &current, &rustacean),
+            )?;
+            Ok(Tagged::new(&rustacean, json!(rustacean)))
+        })
+    })
+    .await
+}
+
+#[rocket::patch("/rustaceans/<id>", data = "<rustacean_patch>")]
+pub async fn patch_rustacean(
+    id: i32,
+    rustacean_patch: Json<RustaceanPatch>,
+    db: DbConnection,
+    user: Authorized<RustaceanUpdate>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<Tagged<Value>, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        RustaceanRepository::update(connection, id, rustacean.into_inner())
-            .map(|rustacean| json!(rustacean))
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let current = RustaceanRepository::find_for_update(connection, id)
+                .map_err(rustacean_not_found)?;
+            if_match.verify(&current)?;
+            let rustacean =
+                RustaceanRepository::patch(connection, id, rustacean_patch.into_inner())
+                    .map_err(rustacean_not_found)?;
+            events::publish(
+                connection,
+                &mailer.0,
+                &Event::updated(&actor, &current, &rustacean),
+            )?;
+            Ok(Tagged::new(&rustacean, json!(rustacean)))
+        })
     })
     .await
 }
@@ -80,12 +143,23 @@ pub async fn update_rustacean(
 pub async fn delete_rustacean(
     id: i32,
     db: DbConnection,
-    _user: EditorUser,
-) -> Result<NoContent, Custom<Value>> {
+    user: Authorized<RustaceanDelete>,
+    if_match: IfMatch,
+    request_id: RequestId,
+    mailer: Mailer,
+) -> Result<NoContent, ApiError> {
+    let actor = user.actor(&request_id);
     db.run(move |connection| {
-        RustaceanRepository::delete(connection, id)
-            .map(|_| NoContent)
-            .map_err(|e| server_error(e.into()))
+        connection.transaction(|connection| {
+            let current = RustaceanRepository::find_for_update(connection, id)
+                .map_err(rustacean_not_found)?;
+            if_match.verify(&current)?;
+            if RustaceanRepository::delete(connection, id)? == 0 {
+                return Err(ApiError::NotFound("Rustacean not found".to_string()));
+            }
+            events::publish(connection, &mailer.0, &Event::deleted(&actor, &current))?;
+            Ok(NoContent)
+        })
     })
     .await
 }
@@ -0,0 +1,117 @@
+use rocket::{
+    http::Status,
+    response::content::RawHtml,
+    response::status::{Custom, NoContent},
+    serde::json::{json, Json, Value},
+};
+use tera::Context;
+
+use crate::{
+    auth::{self, TokenError},
+    models::{NewDigestSubscription, SessionUser},
+    repositories::{DigestSubscriptionRepository, RustaceanRepository},
+    rocket_routes::{DbConnection, Mailer},
+};
+
+use super::ApiError;
+
+const UNSUBSCRIBE_PAGE: &str = "pages/unsubscribe.html";
+
+fn validate(new_subscription: &mut NewDigestSubscription) -> Result<(), ApiError> {
+    new_subscription
+        .email
+        .parse::<lettre::Address>()
+        .map_err(|_| {
+            ApiError::UnprocessableEntity(format!(
+                "Invalid email address '{}'",
+                new_subscription.email
+            ))
+        })?;
+    new_subscription.keyword = new_subscription
+        .keyword
+        .take()
+        .map(|keyword| keyword.trim().to_string())
+        .filter(|keyword| !keyword.is_empty());
+    Ok(())
+}
+
+#[rocket::get("/me/subscriptions")]
+pub async fn get_subscriptions(db: DbConnection, user: SessionUser) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        DigestSubscriptionRepository::find_by_user(connection, user.id)
+            .map(|subscriptions| json!(subscriptions))
+            .map_err(ApiError::from)
+    })
+    .await
+}
+
+#[rocket::post("/me/subscriptions", format = "json", data = "<new_subscription>")]
+pub async fn create_subscription(
+    new_subscription: Json<NewDigestSubscription>,
+    db: DbConnection,
+    user: SessionUser,
+) -> Result<Custom<Value>, ApiError> {
+    let mut new_subscription = new_subscription.into_inner();
+    validate(&mut new_subscription)?;
+    new_subscription.user_id = user.id;
+    db.run(move |connection| {
+        if let Some(rustacean_id) = new_subscription.rustacean_id {
+            RustaceanRepository::find(connection, rustacean_id).map_err(|e| match e {
+                diesel::result::Error::NotFound => ApiError::UnprocessableEntity(format!(
+                    "Rustacean {} does not exist",
+                    rustacean_id
+                )),
+                _ => e.into(),
+            })?;
+        }
+        DigestSubscriptionRepository::create(connection, new_subscription)
+            .map(|subscription| Custom(Status::Created, json!(subscription)))
+            .map_err(ApiError::from)
+    })
+    .await
+}
+
+#[rocket::delete("/me/subscriptions/<id>")]
+pub async fn delete_subscription(
+    id: i32,
+    db: DbConnection,
+    user: SessionUser,
+) -> Result<NoContent, ApiError> {
+    db.run(move |connection| {
+        match DigestSubscriptionRepository::delete_for_user(connection, user.id, id)? {
+            0 => Err(ApiError::NotFound("Subscription not found".to_string())),
+            _ => Ok(NoContent),
+        }
+    })
+    .await
+}
+
+fn unsubscribe_error(e: TokenError) -> ApiError {
+    match e {
+        TokenError::Invalid => ApiError::BadRequest("Invalid unsubscribe token".to_string()),
+        TokenError::Expired => ApiError::BadRequest("Expired unsubscribe token".to_string()),
+        TokenError::MissingSecret => ApiError::Internal(e.to_string()),
+    }
+}
+
+/// Target of the unsubscribe link in the digest, no login required. Asks for a
+/// confirmation so that link scanners following it do not unsubscribe anyone.
+#[rocket::get("/unsubscribe?<token>")]
+pub async fn unsubscribe(token: String, mailer: Mailer) -> Result<RawHtml<String>, ApiError> {
+    auth::verify_unsubscribe_token(&token).map_err(unsubscribe_error)?;
+    let mut context = Context::new();
+    context.insert("token", &token);
+    mailer.render_page(UNSUBSCRIBE_PAGE, &context)
+}
+
+/// Unsubscribes, from the confirmation page or as the one-click unsubscribe (RFC 8058)
+/// of mail clients using the `List-Unsubscribe` header.
+#[rocket::post("/unsubscribe?<token>")]
+pub async fn unsubscribe_one_click(token: String, db: DbConnection) -> Result<Value, ApiError> {
+    let id = auth::verify_unsubscribe_token(&token).map_err(unsubscribe_error)?;
+    db.run(move |connection| {
+        DigestSubscriptionRepository::delete(connection, id)?;
+        Ok(json!({ "unsubscribed": true }))
+    })
+    .await
+}
@@ -0,0 +1,175 @@
+use rocket::{
+    http::Status,
+    response::status::{Custom, NoContent},
+    serde::json::{json, Json, Value},
+};
+
+use crate::{
+    config,
+    events::EVENT_NAMES,
+    models::{NewWebhook, WebhookChangeset},
+    repositories::{WebhookDeliveryRepository, WebhookRepository},
+    rocket_routes::{Authorized, DbConnection, WebhookManage},
+    webhooks,
+};
+
+use super::ApiError;
+
+fn webhook_not_found(e: diesel::result::Error) -> ApiError {
+    match e {
+        diesel::result::Error::NotFound => ApiError::NotFound("Webhook not found".to_string()),
+        _ => e.into(),
+    }
+}
+
+fn validate(url: &str, events: &[String], secret: Option<&str>) -> Result<(), ApiError> {
+    webhooks::check_url(url).map_err(ApiError::UnprocessableEntity)?;
+    if secret.is_some_and(|secret| secret.len() > webhooks::SECRET_MAX_LENGTH) {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Webhook secret cannot be longer than {} bytes",
+            webhooks::SECRET_MAX_LENGTH
+        )));
+    }
+    match events
+        .iter()
+        .find(|event| !EVENT_NAMES.contains(&event.as_str()))
+    {
+        Some(event) => Err(ApiError::UnprocessableEntity(format!(
+            "Unknown event '{}', expected one of {}",
+            event,
+            EVENT_NAMES.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[rocket::get("/webhooks")]
+pub async fn get_webhooks(
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        WebhookRepository::find_multiple(connection)
+            .map(|webhooks| json!(webhooks))
+            .map_err(ApiError::from)
+    })
+    .await
+}
+
+#[rocket::get("/webhooks/<id>")]
+pub async fn view_webhook(
+    id: i32,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        WebhookRepository::find(connection, id)
+            .map(|webhook| json!(webhook))
+            .map_err(webhook_not_found)
+    })
+    .await
+}
+
+/// Creates a webhook, the response is the only one to show its secret.
+#[rocket::post("/webhooks", format = "json", data = "<new_webhook>")]
+pub async fn create_webhook(
+    new_webhook: Json<NewWebhook>,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Custom<Value>, ApiError> {
+    let mut new_webhook = new_webhook.into_inner();
+    validate(
+        &new_webhook.url,
+        &new_webhook.events,
+        Some(&new_webhook.secret),
+    )?;
+    if new_webhook.secret.is_empty() {
+        new_webhook.secret = webhooks::generate_secret();
+    }
+    db.run(move |connection| {
+        let webhook = WebhookRepository::create(connection, new_webhook)?;
+        let mut body = json!(webhook);
+        body["secret"] = json!(webhook.secret);
+        Ok(Custom(Status::Created, body))
+    })
+    .await
+}
+
+#[rocket::put("/webhooks/<id>", format = "json", data = "<webhook>")]
+pub async fn update_webhook(
+    id: i32,
+    webhook: Json<WebhookChangeset>,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Value, ApiError> {
+    let webhook = webhook.into_inner();
+    validate(&webhook.url, &webhook.events, webhook.secret.as_deref())?;
+    if webhook.secret.as_deref() == Some("") {
+        return Err(ApiError::UnprocessableEntity(
+            "Webhook secret cannot be empty".to_string(),
+        ));
+    }
+    db.run(move |connection| {
+        WebhookRepository::update(connection, id, webhook)
+            .map(|webhook| json!(webhook))
+            .map_err(webhook_not_found)
+    })
+    .await
+}
+
+#[rocket::delete("/webhooks/<id>")]
+pub async fn delete_webhook(
+    id: i32,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<NoContent, ApiError> {
+    db.run(
+        move |connection| match WebhookRepository::delete(connection, id)? {
+            0 => Err(ApiError::NotFound("Webhook not found".to_string())),
+            _ => Ok(NoContent),
+        },
+    )
+    .await
+}
+
+/// Delivery log of a webhook, most recent first.
+#[rocket::get("/webhooks/<id>/deliveries?<limit>")]
+pub async fn get_webhook_deliveries(
+    id: i32,
+    limit: Option<i64>,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Value, ApiError> {
+    db.run(move |connection| {
+        let webhook = WebhookRepository::find(connection, id).map_err(webhook_not_found)?;
+        WebhookDeliveryRepository::find_by_webhook(
+            connection,
+            &webhook,
+            limit.unwrap_or(config::get().page_limits.webhook_deliveries),
+        )
+        .map(|deliveries| json!(deliveries))
+        .map_err(ApiError::from)
+    })
+    .await
+}
+
+/// Queues a delivery again, it is sent on the next run of the webhooks job.
+#[rocket::post("/webhooks/<id>/deliveries/<delivery_id>/redeliver")]
+pub async fn redeliver_webhook(
+    id: i32,
+    delivery_id: i32,
+    db: DbConnection,
+    _user: Authorized<WebhookManage>,
+) -> Result<Custom<Value>, ApiError> {
+    db.run(move |connection| {
+        WebhookDeliveryRepository::redeliver(connection, id, delivery_id)
+            .map(|delivery| Custom(Status::Accepted, json!(delivery)))
+            .map_err(|e| match e {
+                diesel::result::Error::NotFound => {
+                    ApiError::NotFound("Delivery not found".to_string())
+                }
+                _ => e.into(),
+            })
+    })
+    .await
+}
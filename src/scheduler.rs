@@ -0,0 +1,285 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use cron::Schedule;
+use diesel::PgConnection;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Orbit, Rocket};
+use serde::Serialize;
+
+use crate::config;
+use crate::digest;
+use crate::mail::HtmlMailer;
+use crate::models::{DigestFrequency, JobRun};
+use crate::outbox;
+use crate::repositories::JobRunRepository;
+use crate::rocket_routes::{DbConnection, Mailer};
+use crate::webhooks;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Work a job performs, also the name under which its runs are recorded.
+#[derive(Clone, Copy, Debug)]
+pub enum Task {
+    /// Queues the digests of every subscription with the given frequency.
+    Digest(DigestFrequency),
+    /// Delivers the due emails of the outbox.
+    Outbox,
+    /// Delivers the due webhook calls.
+    Webhooks,
+}
+
+impl Task {
+    fn run(
+        &self,
+        connection: &mut PgConnection,
+        mailer: &HtmlMailer,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            Task::Digest(frequency) => {
+                let queued =
+                    digest::queue_subscriber_digests(connection, mailer, Some(*frequency), None)?;
+                log::info!("Queued {} {} digests", queued, frequency);
+                Ok(())
+            }
+            Task::Outbox => {
+                let report = outbox::deliver_due(connection, mailer.transport.as_ref(), None)?;
+                log::info!("Outbox delivery: {}", report);
+                Ok(())
+            }
+            Task::Webhooks => {
+                let report = webhooks::deliver_due(connection)?;
+                log::info!("Webhook delivery: {}", report);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Task {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Task::Digest(frequency) => write!(f, "digest-{}", frequency),
+            Task::Outbox => write!(f, "outbox"),
+            Task::Webhooks => write!(f, "webhooks"),
+        }
+    }
+}
+
+impl FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "outbox" => return Ok(Task::Outbox),
+            "webhooks" => return Ok(Task::Webhooks),
+            _ => {}
+        }
+        match s.strip_prefix("digest-") {
+            Some(frequency) => Ok(Task::Digest(frequency.parse()?)),
+            None => Err(format!("Unknown scheduler job '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub task: Task,
+    pub schedule: Schedule,
+}
+
+impl Job {
+    pub fn name(&self) -> String {
+        self.task.to_string()
+    }
+
+    pub fn next_run_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.schedule
+            .after(&after.and_utc())
+            .next()
+            .map(|next| next.naive_utc())
+    }
+
+    /// Runs the job unless another instance holds its lock or it is not due yet.
+    /// `baseline` stands in for the last run of jobs that never ran.
+    fn run_if_due(
+        &self,
+        connection: &mut PgConnection,
+        mailer: &HtmlMailer,
+        baseline: NaiveDateTime,
+    ) -> Result<Option<JobRun>, diesel::result::Error> {
+        let name = self.name();
+        if !JobRunRepository::try_lock(connection, &name)? {
+            return Ok(None);
+        }
+        let job_run = self.run_locked(connection, mailer, baseline);
+        JobRunRepository::unlock(connection, &name)?;
+        job_run
+    }
+
+    /// Runs the job while holding its lock. The task runs outside of any transaction,
+    /// so what it delivers is committed item by item, as `outbox::deliver_due` does,
+    /// and survives a failure of the rest of the run.
+    fn run_locked(
+        &self,
+        connection: &mut PgConnection,
+        mailer: &HtmlMailer,
+        baseline: NaiveDateTime,
+    ) -> Result<Option<JobRun>, diesel::result::Error> {
+        let name = self.name();
+        let last_started_at = JobRunRepository::find(connection, &name)?
+            .map(|job_run| job_run.last_started_at)
+            .unwrap_or(baseline);
+        let started_at = Utc::now().naive_utc();
+        let due = self
+            .next_run_after(last_started_at)
+            .is_some_and(|next| next <= started_at);
+        if !due {
+            return Ok(None);
+        }
+
+        log::info!("Running scheduled job {}", name);
+        let result = self.task.run(connection, mailer);
+        let job_run = JobRun {
+            name: name.clone(),
+            last_started_at: started_at,
+            last_finished_at: Some(Utc::now().naive_utc()),
+            last_status: if result.is_ok() { "ok" } else { "failed" }.to_string(),
+            last_error: result.err().map(|e| {
+                log::error!("Scheduled job {} failed: {}", name, e);
+                e.to_string()
+            }),
+        };
+        JobRunRepository::save(connection, &job_run).map(Some)
+    }
+}
+
+/// Jobs configured for this instance, managed as state for the admin routes.
+#[derive(Clone, Debug, Default)]
+pub struct Jobs(pub Arc<Vec<Job>>);
+
+impl Jobs {
+    /// Parses `;` separated `job=cron expression` entries such as
+    /// `digest-daily=0 0 7 * * *;digest-weekly=0 0 7 * * Mon;outbox=0 * * * * *;webhooks=0 * * * * *`.
+    pub fn parse(jobs: &str) -> Result<Self, String> {
+        let jobs = jobs
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (task, expression) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid scheduler job '{}'", entry))?;
+                let schedule = Schedule::from_str(expression.trim()).map_err(|e| {
+                    format!("Invalid cron expression '{}': {}", expression.trim(), e)
+                })?;
+                Ok(Job {
+                    task: task.trim().parse()?,
+                    schedule,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Jobs(Arc::new(jobs)))
+    }
+
+    pub fn status(&self, job_runs: Vec<JobRun>) -> Vec<JobStatus> {
+        let now = Utc::now().naive_utc();
+        self.0
+            .iter()
+            .map(|job| {
+                let name = job.name();
+                JobStatus {
+                    schedule: job.schedule.to_string(),
+                    next_run_at: job.next_run_after(now),
+                    last_run: job_runs
+                        .iter()
+                        .find(|job_run| job_run.name == name)
+                        .cloned(),
+                    name,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Runs the jobs configured in `SCHEDULER_JOBS` once the server is up.
+pub struct Scheduler;
+
+#[rocket::async_trait]
+impl Fairing for Scheduler {
+    fn info(&self) -> Info {
+        Info {
+            name: "Scheduler for periodic jobs",
+            kind: Kind::Ignite | Kind::Liftoff,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        match Jobs::parse(&config::get().scheduler.jobs) {
+            Ok(jobs) => Ok(rocket.manage(jobs)),
+            Err(e) => {
+                log::error!("Cannot load scheduler jobs: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let jobs = match rocket.state::<Jobs>() {
+            Some(jobs) if !jobs.0.is_empty() => jobs.clone(),
+            _ => return,
+        };
+        let mailer = match rocket.state::<Mailer>() {
+            Some(mailer) => mailer.clone(),
+            None => {
+                log::error!("Scheduler disabled, no mailer");
+                return;
+            }
+        };
+        let pool = match DbConnection::pool(rocket) {
+            Some(pool) => pool.clone(),
+            None => {
+                log::error!("Scheduler disabled, no database pool");
+                return;
+            }
+        };
+        let baseline = Utc::now().naive_utc();
+
+        rocket::tokio::spawn(async move {
+            let mut interval = rocket::tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let connection = match pool.get().await {
+                    Some(connection) => connection,
+                    None => {
+                        log::error!("Scheduler cannot get a database connection");
+                        continue;
+                    }
+                };
+                let jobs = jobs.clone();
+                let mailer = mailer.clone();
+                connection
+                    .run(move |connection| {
+                        for job in jobs.0.iter() {
+                            if let Err(e) = job.run_if_due(connection, &mailer.0, baseline) {
+                                log::error!("Cannot run scheduled job {}: {}", job.name(), e);
+                            }
+                        }
+                    })
+                    .await;
+            }
+        });
+    }
+}
+
+/// Status of a configured job as reported to admins.
+#[derive(Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub next_run_at: Option<NaiveDateTime>,
+    pub last_run: Option<JobRun>,
+}
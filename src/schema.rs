@@ -1,5 +1,22 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_events (id) {
+        id -> Int4,
+        actor_id -> Nullable<Int4>,
+        #[max_length = 64]
+        entity -> Varchar,
+        entity_id -> Int4,
+        #[max_length = 16]
+        action -> Varchar,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        #[max_length = 64]
+        request_id -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     crates (id) {
         id -> Int4,
@@ -12,6 +29,71 @@ diesel::table! {
         version -> Varchar,
         description -> Nullable<Text>,
         created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    digest_subscriptions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 254]
+        email -> Varchar,
+        #[max_length = 16]
+        frequency -> Varchar,
+        rustacean_id -> Nullable<Int4>,
+        #[max_length = 128]
+        keyword -> Nullable<Varchar>,
+        last_sent_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    email_outbox (id) {
+        id -> Int4,
+        #[max_length = 254]
+        sender -> Nullable<Varchar>,
+        recipients -> Array<Text>,
+        subject -> Nullable<Text>,
+        message -> Bytea,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notification_preferences (user_id) {
+        user_id -> Int4,
+        #[max_length = 254]
+        email -> Varchar,
+        crate_updated -> Bool,
+        crate_deleted -> Bool,
+        email_verified_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    permissions (id) {
+        id -> Int4,
+        #[max_length = 64]
+        code -> Varchar,
+        #[max_length = 256]
+        description -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    role_permissions (id) {
+        id -> Int4,
+        role_id -> Int4,
+        permission_id -> Int4,
     }
 }
 
@@ -32,6 +114,19 @@ diesel::table! {
         name -> Varchar,
         email -> Varchar,
         created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    scheduled_jobs (name) {
+        #[max_length = 64]
+        name -> Varchar,
+        last_started_at -> Timestamp,
+        last_finished_at -> Nullable<Timestamp>,
+        #[max_length = 16]
+        last_status -> Varchar,
+        last_error -> Nullable<Text>,
     }
 }
 
@@ -54,8 +149,60 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        #[max_length = 64]
+        event -> Varchar,
+        payload -> Jsonb,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        response_status -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Int4,
+        #[max_length = 2048]
+        url -> Varchar,
+        events -> Array<Text>,
+        #[max_length = 128]
+        secret -> Varchar,
+        active -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::joinable!(crates -> rustaceans (rustacean_id));
+diesel::joinable!(digest_subscriptions -> rustaceans (rustacean_id));
+diesel::joinable!(digest_subscriptions -> users (user_id));
+diesel::joinable!(notification_preferences -> users (user_id));
+diesel::joinable!(role_permissions -> permissions (permission_id));
+diesel::joinable!(role_permissions -> roles (role_id));
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
 
-diesel::allow_tables_to_appear_in_same_query!(crates, roles, rustaceans, user_roles, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    audit_events,
+    crates,
+    digest_subscriptions,
+    email_outbox,
+    notification_preferences,
+    permissions,
+    role_permissions,
+    roles,
+    rustaceans,
+    scheduled_jobs,
+    user_roles,
+    users,
+    webhook_deliveries,
+    webhooks,
+);
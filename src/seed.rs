@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use diesel::{Connection, PgConnection};
+
+use crate::auth;
+use crate::events::{self, Event};
+use crate::mail::HtmlMailer;
+use crate::models::{Actor, NewCrate, NewRustacean, NewUser, RoleCode};
+use crate::repositories::{CrateRepository, RoleRepository, RustaceanRepository, UserRepository};
+
+/// Which data set `seed` creates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeedProfile {
+    /// Browsable sample data for local development.
+    Demo,
+    /// The users and fixtures the integration tests log in with.
+    Test,
+}
+
+impl FromStr for SeedProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "demo" => Ok(SeedProfile::Demo),
+            "test" => Ok(SeedProfile::Test),
+            _ => Err(format!("Unknown seed profile '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for SeedProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeedProfile::Demo => write!(f, "demo"),
+            SeedProfile::Test => write!(f, "test"),
+        }
+    }
+}
+
+struct SeedUser {
+    username: &'static str,
+    password: &'static str,
+    role: RoleCode,
+}
+
+struct SeedCrate {
+    code: &'static str,
+    name: &'static str,
+    version: &'static str,
+    description: &'static str,
+}
+
+struct SeedRustacean {
+    name: &'static str,
+    email: &'static str,
+    crates: &'static [SeedCrate],
+}
+
+struct SeedData {
+    users: &'static [SeedUser],
+    rustaceans: &'static [SeedRustacean],
+}
+
+const TEST_DATA: SeedData = SeedData {
+    users: &[
+        SeedUser {
+            username: "test_admin",
+            password: "1234",
+            role: RoleCode::Admin,
+        },
+        SeedUser {
+            username: "test_editor",
+            password: "1234",
+            role: RoleCode::Editor,
+        },
+        SeedUser {
+            username: "test_viewer",
+            password: "1234",
+            role: RoleCode::Viewer,
+        },
+    ],
+    rustaceans: &[SeedRustacean {
+        name: "Test Rustacean",
+        email: "test.rustacean@cr8s.com",
+        crates: &[SeedCrate {
+            code: "test_crate",
+            name: "Test crate",
+            version: "1.0.0",
+            description: "Crate seeded for the integration tests",
+        }],
+    }],
+};
+
+const DEMO_DATA: SeedData = SeedData {
+    users: &[
+        SeedUser {
+            username: "demo_admin",
+            password: "demo1234",
+            role: RoleCode::Admin,
+        },
+        SeedUser {
+            username: "demo_editor",
+            password: "demo1234",
+            role: RoleCode::Editor,
+        },
+        SeedUser {
+            username: "demo_viewer",
+            password: "demo1234",
+            role: RoleCode::Viewer,
+        },
+    ],
+    rustaceans: &[
+        SeedRustacean {
+            name: "Ferris Crab",
+            email: "ferris@cr8s.com",
+            crates: &[
+                SeedCrate {
+                    code: "shell",
+                    name: "Shell",
+                    version: "0.3.1",
+                    description: "Hard-shelled process management",
+                },
+                SeedCrate {
+                    code: "claws",
+                    name: "Claws",
+                    version: "1.2.0",
+                    description: "Grip onto async tasks",
+                },
+            ],
+        },
+        SeedRustacean {
+            name: "Corro Unsafe",
+            email: "corro@cr8s.com",
+            crates: &[SeedCrate {
+                code: "raw_parts",
+                name: "Raw parts",
+                version: "0.1.0",
+                description: "Pointer juggling, handle with care",
+            }],
+        },
+    ],
+};
+
+/// What a seed created, existing rows are counted as skipped.
+#[derive(Default)]
+pub struct SeedReport {
+    pub created: usize,
+    pub skipped: usize,
+    /// Existing users whose password or roles were reset, their sessions are outdated.
+    pub updated_users: Vec<i32>,
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} created, {} already present",
+            self.created, self.skipped
+        )
+    }
+}
+
+impl SeedReport {
+    fn record(&mut self, created: bool) {
+        if created {
+            self.created += 1;
+        } else {
+            self.skipped += 1;
+        }
+    }
+}
+
+type SeedResult<T> = Result<T, Box<dyn Error>>;
+
+fn seed_user(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    seed_user: &SeedUser,
+    report: &mut SeedReport,
+) -> SeedResult<()> {
+    let password_hash =
+        || auth::hash_password(seed_user.password.to_string()).map_err(|e| e.to_string());
+    let username = seed_user.username.to_string();
+    let user = match UserRepository::find_by_username(connection, &username) {
+        Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => {
+            let new_user = NewUser {
+                username,
+                password: password_hash()?,
+            };
+            let user = UserRepository::create(connection, new_user, vec![seed_user.role])?;
+            events::publish(connection, mailer, &Event::created(&Actor::cli(), &user))?;
+            report.record(true);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Existing users are brought back to the seeded password and role
+    let mut updated = false;
+    if auth::verify_password(&user, seed_user.password).is_err() {
+        UserRepository::update_password(connection, user.id, password_hash()?)?;
+        updated = true;
+    }
+    let roles = RoleRepository::find_by_user(connection, &user)?;
+    if !roles.iter().any(|role| role.code == seed_user.role) {
+        let role = RoleRepository::find_by_code(connection, &seed_user.role)?;
+        UserRepository::assign_role(connection, &user, &role)?;
+        updated = true;
+    }
+    if updated {
+        report.updated_users.push(user.id);
+    }
+    report.record(false);
+    Ok(())
+}
+
+/// Creates the users, rustaceans and crates of the profile that are missing, identified
+/// by username, email and crate code. The roles come from the migrations. What is created
+/// is published like the mutations of the API, webhooks and notifications included.
+pub fn seed(
+    connection: &mut PgConnection,
+    mailer: &HtmlMailer,
+    profile: SeedProfile,
+) -> SeedResult<SeedReport> {
+    let data = match profile {
+        SeedProfile::Demo => &DEMO_DATA,
+        SeedProfile::Test => &TEST_DATA,
+    };
+    connection.transaction(|connection| {
+        let mut report = SeedReport::default();
+        for user in data.users {
+            seed_user(connection, mailer, user, &mut report)?;
+        }
+        for seed_rustacean in data.rustaceans {
+            let rustacean =
+                match RustaceanRepository::find_by_email(connection, seed_rustacean.email)? {
+                    Some(rustacean) => {
+                        report.record(false);
+                        rustacean
+                    }
+                    None => {
+                        let new_rustacean = NewRustacean {
+                            name: seed_rustacean.name.to_string(),
+                            email: seed_rustacean.email.to_string(),
+                        };
+                        let rustacean = RustaceanRepository::create(connection, new_rustacean)?;
+                        events::publish(
+                            connection,
+                            mailer,
+                            &Event::created(&Actor::cli(), &rustacean),
+                        )?;
+                        report.record(true);
+                        rustacean
+                    }
+                };
+            for seed_crate in seed_rustacean.crates {
+                if CrateRepository::find_by_code(connection, seed_crate.code)?.is_some() {
+                    report.record(false);
+                    continue;
+                }
+                let new_crate = NewCrate {
+                    rustacean_id: rustacean.id,
+                    code: seed_crate.code.to_string(),
+                    name: seed_crate.name.to_string(),
+                    version: seed_crate.version.to_string(),
+                    description: Some(seed_crate.description.to_string()),
+                };
+                let a_crate = CrateRepository::create(connection, new_crate)?;
+                events::publish(connection, mailer, &Event::created(&Actor::cli(), &a_crate))?;
+                report.record(true);
+            }
+        }
+        Ok::<SeedReport, Box<dyn Error>>(report)
+    })
+}
@@ -0,0 +1,249 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::{Connection, PgConnection, QueryResult};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde_json::json;
+
+use crate::auth;
+use crate::config;
+use crate::events::Event;
+use crate::models::{Auditable, NewWebhookDelivery, Webhook, WebhookDelivery};
+use crate::outbox::{self, DeliveryReport};
+use crate::repositories::{WebhookDeliveryRepository, WebhookRepository};
+
+const EVENT_HEADER: &str = "X-Cr8s-Event";
+const DELIVERY_HEADER: &str = "X-Cr8s-Delivery";
+const SIGNATURE_HEADER: &str = "X-Cr8s-Signature";
+const TIMESTAMP_HEADER: &str = "X-Cr8s-Timestamp";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery stays hidden from other workers, well past `REQUEST_TIMEOUT`.
+const LEASE_SECONDS: i32 = 60;
+const SECRET_LENGTH: usize = 40;
+/// Width of `webhooks.secret`.
+pub const SECRET_MAX_LENGTH: usize = 128;
+
+pub fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Whether an address can be reached from the internet, as opposed to loopback,
+/// link-local, private, shared or reserved ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || first == 0
+                || first >= 240
+                || (first == 100 && (second & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Checks the URL of a webhook when it is saved. Hosts given as addresses, or as
+/// `localhost`, must be public unless `webhooks.allow_private_hosts` is set; names are
+/// checked again once resolved, on every delivery.
+pub fn check_url(url: &str) -> Result<(), String> {
+    let invalid = || format!("Invalid webhook URL '{}'", url);
+    let url = Url::parse(url).map_err(|_| invalid())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid());
+    }
+    let host = url.host_str().ok_or_else(invalid)?;
+    let is_private = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => !is_public(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+    };
+    if is_private && !config::get().webhooks.allow_private_hosts {
+        return Err(format!(
+            "Webhook URL '{}' does not point to a public host",
+            url
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves the host of a webhook, refusing non-public addresses unless
+/// `webhooks.allow_private_hosts` is set.
+fn resolve(url: &Url) -> Result<SocketAddr, String> {
+    let addresses = url
+        .socket_addrs(|| None)
+        .map_err(|e| format!("Cannot resolve {}: {}", url, e))?;
+    if !config::get().webhooks.allow_private_hosts {
+        if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+            return Err(format!(
+                "{} resolves to {}, which is not a public address",
+                url,
+                address.ip()
+            ));
+        }
+    }
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{} resolves to no address", url))
+}
+
+/// Queues a delivery of the event to every active webhook listening to it,
+/// within the transaction of the mutation.
+pub fn enqueue<T: Auditable>(connection: &mut PgConnection, event: &Event<T>) -> QueryResult<()> {
+    let name = event.name();
+    let webhooks: Vec<Webhook> = WebhookRepository::find_active(connection)?
+        .into_iter()
+        .filter(|webhook| webhook.accepts(&name))
+        .collect();
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let payload = json!({
+        "event": name,
+        "occurred_at": Utc::now().naive_utc(),
+        "actor_id": event.actor.user_id,
+        "data": event.entity(),
+        "previous": event.after.and(event.before),
+    });
+    let new_deliveries = webhooks
+        .iter()
+        .map(|webhook| NewWebhookDelivery {
+            webhook_id: webhook.id,
+            event: name.clone(),
+            payload: payload.clone(),
+        })
+        .collect();
+    WebhookDeliveryRepository::create(connection, new_deliveries)?;
+    Ok(())
+}
+
+/// A failed call, with the response status when the endpoint answered.
+struct DeliveryFailure {
+    response_status: Option<i32>,
+    error: String,
+}
+
+/// Calls the webhook without following redirects, pinned to the address `resolve` checked
+/// so that a second DNS answer cannot point it elsewhere.
+fn deliver(delivery: &WebhookDelivery, webhook: &Webhook) -> Result<i32, DeliveryFailure> {
+    let failure = |error: String| DeliveryFailure {
+        response_status: None,
+        error,
+    };
+    let url = Url::parse(&webhook.url).map_err(|e| failure(e.to_string()))?;
+    let address = resolve(&url).map_err(failure)?;
+    let mut client = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent("cr8s-webhooks")
+        .redirect(Policy::none());
+    if let Some(domain) = url.domain() {
+        client = client.resolve(domain, address);
+    }
+    let client = client.build().map_err(|e| failure(e.to_string()))?;
+
+    let body = delivery.payload.to_string();
+    let timestamp = Utc::now().timestamp().to_string();
+    let response = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(TIMESTAMP_HEADER, &timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            auth::sign_webhook_payload(&webhook.secret, &timestamp, body.as_bytes()),
+        )
+        .body(body)
+        .send()
+        .map_err(|e| failure(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16().into())
+    } else {
+        Err(DeliveryFailure {
+            response_status: Some(status.as_u16().into()),
+            error: format!("Endpoint responded with {}", status),
+        })
+    }
+}
+
+/// Delivers every due webhook call. Each one is claimed with a lease in a short transaction,
+/// so concurrent workers skip it, and called with no transaction or lock held.
+pub fn deliver_due(
+    connection: &mut PgConnection,
+) -> Result<DeliveryReport, Box<dyn std::error::Error>> {
+    let mut report = DeliveryReport::default();
+    loop {
+        let claimed = connection.transaction(|connection| {
+            let delivery = match WebhookDeliveryRepository::lock_next_due(connection)? {
+                Some(delivery) => delivery,
+                None => return Ok(None),
+            };
+            WebhookDeliveryRepository::lease(connection, delivery.id, LEASE_SECONDS)?;
+            let webhook = WebhookRepository::find(connection, delivery.webhook_id)?;
+            Ok::<_, diesel::result::Error>(Some((delivery, webhook)))
+        })?;
+        let (delivery, webhook) = match claimed {
+            Some(claimed) => claimed,
+            None => return Ok(report),
+        };
+        match deliver(&delivery, &webhook) {
+            Ok(response_status) => {
+                WebhookDeliveryRepository::mark_sent(connection, delivery.id, response_status)?;
+                report.sent += 1;
+            }
+            Err(failure) => {
+                let attempts = delivery.attempts + 1;
+                let retry_in_seconds = if attempts >= outbox::MAX_ATTEMPTS {
+                    report.dead += 1;
+                    None
+                } else {
+                    report.retried += 1;
+                    Some(outbox::backoff_seconds(attempts))
+                };
+                log::warn!(
+                    "Cannot deliver webhook call #{} to {}: {}",
+                    delivery.id,
+                    webhook.url,
+                    failure.error
+                );
+                WebhookDeliveryRepository::mark_failed(
+                    connection,
+                    delivery.id,
+                    failure.response_status,
+                    &failure.error,
+                    retry_in_seconds,
+                )?;
+            }
+        }
+    }
+}
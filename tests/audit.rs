@@ -0,0 +1,64 @@
+use common::{create_test_crate, create_test_rustacean, delete_test_crate, delete_test_rustacean};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+pub mod common;
+
+#[test]
+fn test_get_audit_events() {
+    let client_with_editor = common::get_client_with_logged_in_editor();
+    let client_with_admin = common::get_client_with_logged_in_admin();
+    let rustacean = create_test_rustacean(&client_with_editor);
+    let a_crate = create_test_crate(&client_with_editor, &rustacean);
+    let crate_id = a_crate["id"].clone();
+    delete_test_crate(&client_with_editor, a_crate);
+
+    let response = client_with_admin
+        .get(format!("{}/audit?entity=crate", common::APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let json: Value = response.json().unwrap();
+    let events: Vec<&Value> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|event| event["entity_id"] == crate_id)
+        .collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["action"], "delete");
+    assert_eq!(events[0]["after"], Value::Null);
+    assert_eq!(events[0]["before"]["id"], crate_id);
+    assert!(events[0]["actor_id"].is_number());
+    assert!(events[0]["request_id"].is_string());
+    assert_eq!(events[1]["action"], "create");
+    assert_eq!(events[1]["before"], Value::Null);
+    assert_eq!(events[1]["after"]["id"], crate_id);
+
+    delete_test_rustacean(&client_with_editor, rustacean);
+}
+
+#[test]
+fn test_get_audit_events_invalid_since() {
+    let client = common::get_client_with_logged_in_admin();
+
+    let response = client
+        .get(format!("{}/audit?since=yesterday", common::APP_HOST))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_get_audit_events_as_editor() {
+    let client = common::get_client_with_logged_in_editor();
+
+    let response = client
+        .get(format!("{}/audit", common::APP_HOST))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
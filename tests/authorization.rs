@@ -1,9 +1,12 @@
 use common::get_client_with_logged_in_viewer;
+use diesel::{PgConnection, RunQueryDsl};
 use reqwest::{blocking::Client, StatusCode};
 use rocket::form::validate::Len;
 use serde_json::{json, Value};
 
-use crate::common::{create_test_user, delete_test_user};
+use crate::common::{
+    cli_command, create_test_user, delete_test_user, run_cli_with, TestDatabase, REDIS_URL,
+};
 
 pub mod common;
 
@@ -104,4 +107,120 @@ fn test_me() {
     assert_eq!(json["username"], "test_viewer");
     assert!(json.get("created_at").is_some());
     assert!(json.get("password").is_none());
+    assert_eq!(json["roles"], json!(["viewer"]));
+}
+
+#[test]
+fn test_deleting_a_user_revokes_their_sessions() {
+    let username = format!("test_user{}", rand::random::<u32>());
+    let output = create_test_user(&username, "1234");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let user_id = stdout
+        .split("User { id: ")
+        .nth(1)
+        .and_then(|rest| rest.split(',').next())
+        .unwrap()
+        .to_string();
+
+    let response = Client::new()
+        .post(format!("{}/login", common::APP_HOST))
+        .json(&json!({ "username": username, "password": "1234" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: Value = response.json().unwrap();
+    let auth_header = format!("Bearer {}", json["token"].as_str().unwrap());
+    // Unlike /me, listing crates only checks the session, not the users table
+    let get_crates = || {
+        Client::new()
+            .get(format!("{}/crates", common::APP_HOST))
+            .header("Authorization", &auth_header)
+            .send()
+            .unwrap()
+    };
+    assert_eq!(get_crates().status(), StatusCode::OK);
+
+    // Without redis the sessions cannot be revoked, so the user is not deleted
+    let output = cli_command(&["users", "delete", &user_id])
+        .env_remove("REDIS_URL")
+        .env_remove("ROCKET_DATABASES")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Redis is not configured"));
+    assert_eq!(get_crates().status(), StatusCode::OK);
+
+    let output = run_cli_with(&["users", "delete", &user_id], &[("REDIS_URL", REDIS_URL)]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("Sessions of user {} revoked", user_id)));
+
+    assert_eq!(get_crates().status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn test_seed_and_reset_need_redis_to_revoke_sessions() {
+    let database = TestDatabase::create();
+    assert!(database.run_cli(&["db", "migrate"]).status.success());
+    assert!(database
+        .run_cli(&["db", "seed", "--profile", "test"])
+        .status
+        .success());
+    let mut connection = database.connect();
+    diesel::sql_query("UPDATE users SET password = 'changed' WHERE username = 'test_admin'")
+        .execute(&mut connection)
+        .unwrap();
+    let without_redis = |args: &[&str]| {
+        let output = database
+            .cli_command(args)
+            .env_remove("REDIS_URL")
+            .output()
+            .unwrap();
+        println!("{:?}", output);
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Redis is not configured"));
+    };
+
+    // The password reset is rolled back rather than committed with the sessions alive
+    without_redis(&["db", "seed", "--profile", "test"]);
+    let changed = diesel::sql_query(
+        "SELECT id FROM users WHERE username = 'test_admin' AND password = 'changed'",
+    )
+    .execute(&mut connection)
+    .unwrap();
+    assert_eq!(changed, 1);
+
+    let count_users = |connection: &mut PgConnection| {
+        diesel::sql_query("SELECT id FROM users")
+            .execute(connection)
+            .unwrap()
+    };
+    let users = count_users(&mut connection);
+    assert!(users > 0);
+    without_redis(&["db", "reset", "--yes"]);
+    assert_eq!(count_users(&mut connection), users);
+}
+
+#[test]
+fn test_me_without_token() {
+    let client = Client::new();
+
+    let response = client
+        .get(format!("{}/me", common::APP_HOST))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let json: Value = response.json().unwrap();
+    assert_eq!(json["status"], 401);
+    assert_eq!(json["title"], "Unauthorized");
+    assert!(json.get("detail").is_some());
+    assert!(json.get("request_id").is_some());
 }
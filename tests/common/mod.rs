@@ -1,5 +1,9 @@
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
+use std::sync::Once;
 
+use chrono::NaiveDateTime;
+use diesel::{Connection, PgConnection, RunQueryDsl};
 use reqwest::{
     blocking::{Client, ClientBuilder},
     header::{self, HeaderMap, HeaderValue},
@@ -7,7 +11,9 @@ use reqwest::{
 };
 use serde_json::{json, Value};
 
-pub const APP_HOST: &'static str = "http://127.0.0.1:8000";
+pub const APP_HOST: &str = "http://127.0.0.1:8000";
+/// The redis of the server, which commands changing users need to revoke sessions.
+pub const REDIS_URL: &str = "redis://127.0.0.1:6379";
 
 pub fn create_test_rustacean(client: &Client) -> Value {
     let response = client
@@ -43,6 +49,7 @@ pub fn create_test_crate(client: &Client, rustacean: &Value) -> Value {
 pub fn delete_test_rustacean(client: &Client, rustacean: Value) {
     let response = client
         .delete(format!("{}/rustaceans/{}", APP_HOST, rustacean["id"]))
+        .header(header::IF_MATCH, "*")
         .send()
         .unwrap();
     assert_eq!(response.status(), StatusCode::NO_CONTENT);
@@ -51,23 +58,57 @@ pub fn delete_test_rustacean(client: &Client, rustacean: Value) {
 pub fn delete_test_crate(client: &Client, a_crate: Value) {
     let response = client
         .delete(format!("{}/crates/{}", APP_HOST, a_crate["id"]))
+        .header(header::IF_MATCH, "*")
         .send()
         .unwrap();
     assert_eq!(response.status(), StatusCode::NO_CONTENT);
 }
 
-pub fn create_test_user(username: &str, password: &str) -> Output {
-    Command::new("cargo")
+/// The `created_at` or `updated_at` timestamp of an entity returned by the API.
+pub fn timestamp(value: &Value) -> NaiveDateTime {
+    value.as_str().unwrap().parse().unwrap()
+}
+
+pub fn get_etag(client: &Client, url: &str) -> String {
+    let response = client.get(url).send().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/// The `cli` binary with `args`, for runs that need more than `run_cli`. Mail goes to
+/// stdout unless the test sets `MAIL_TRANSPORT`, there is no SMTP server to configure.
+pub fn cli_command(args: &[&str]) -> Command {
+    let mut command = Command::new("cargo");
+    command
         .arg("run")
         .arg("--bin")
         .arg("cli")
-        .arg("users")
-        .arg("create")
-        .arg(username)
-        .arg(password)
-        .arg("admin")
+        .args(args)
+        .env("MAIL_TRANSPORT", "stdout");
+    command
+}
+
+/// Runs the `cli` binary with `args` and prints its output.
+pub fn run_cli(args: &[&str]) -> Output {
+    run_cli_with(args, &[])
+}
+
+/// Runs the `cli` binary with `args` and the extra environment `envs` and prints its output.
+pub fn run_cli_with(args: &[&str], envs: &[(&str, &str)]) -> Output {
+    let output = cli_command(args)
+        .envs(envs.iter().copied())
         .output()
-        .unwrap()
+        .unwrap();
+    println!("{:?}", output);
+    output
+}
+
+pub fn create_test_user(username: &str, password: &str) -> Output {
+    run_cli(&["users", "create", username, password, "admin"])
 }
 
 pub fn delete_test_user(create_stdout: String) {
@@ -77,31 +118,22 @@ pub fn delete_test_user(create_stdout: String) {
     let end_bytes = create_stdout.find(suffix).unwrap_or(create_stdout.len());
     let user_id = &create_stdout[start_bytes..end_bytes];
 
-    let _ = Command::new("cargo")
-        .arg("run")
-        .arg("--bin")
-        .arg("cli")
-        .arg("users")
-        .arg("delete")
-        .arg(user_id)
-        .status();
+    let output = run_cli_with(&["users", "delete", user_id], &[("REDIS_URL", REDIS_URL)]);
+    assert!(output.status.success());
 }
 
-pub fn get_logged_in_client(username: &str, role: &str) -> Client {
-    let password = "1234";
-    let output = Command::new("cargo")
-        .arg("run")
-        .arg("--bin")
-        .arg("cli")
-        .arg("users")
-        .arg("create")
-        .arg(username)
-        .arg(password)
-        .arg(role)
-        .output()
-        .unwrap();
+/// Creates the users of the `test` seed profile, once per test binary.
+pub fn seed_test_profile() {
+    static SEED: Once = Once::new();
+    SEED.call_once(|| {
+        let output = run_cli(&["db", "seed", "--profile", "test"]);
+        assert!(output.status.success());
+    });
+}
 
-    println!("{:?}", output);
+pub fn get_logged_in_client(username: &str) -> Client {
+    let password = "1234";
+    seed_test_profile();
 
     let client = Client::new();
     let response = client
@@ -130,9 +162,105 @@ pub fn get_logged_in_client(username: &str, role: &str) -> Client {
 }
 
 pub fn get_client_with_logged_in_viewer() -> Client {
-    get_logged_in_client("test_viewer", "viewer")
+    get_logged_in_client("test_viewer")
 }
 
 pub fn get_client_with_logged_in_editor() -> Client {
-    get_logged_in_client("test_editor", "editor")
+    get_logged_in_client("test_editor")
+}
+
+pub fn get_client_with_logged_in_admin() -> Client {
+    get_logged_in_client("test_admin")
+}
+
+/// Reads every message written by the file transport as (message, envelope) pairs.
+pub fn read_messages(mail_dir: &Path) -> Vec<(String, Value)> {
+    let mut emls: Vec<PathBuf> = std::fs::read_dir(mail_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+        .collect();
+    emls.sort();
+    let messages = emls
+        .iter()
+        .map(|eml| {
+            let envelope = std::fs::read_to_string(eml.with_extension("json")).unwrap();
+            (
+                std::fs::read_to_string(eml).unwrap(),
+                serde_json::from_str(&envelope).unwrap(),
+            )
+        })
+        .collect();
+    std::fs::remove_dir_all(mail_dir).unwrap();
+    messages
+}
+
+/// Reads the messages written by the file transport for any of `recipients`,
+/// leaving out other emails the outbox delivered along the way.
+pub fn read_messages_to(mail_dir: &Path, recipients: &[&str]) -> Vec<(String, Value)> {
+    read_messages(mail_dir)
+        .into_iter()
+        .filter(|(_, envelope)| {
+            envelope["forward_path"].as_array().is_some_and(|to| {
+                to.iter()
+                    .any(|to| recipients.contains(&to.as_str().unwrap()))
+            })
+        })
+        .collect()
+}
+
+pub fn temp_mail_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("cr8s_mail_{}", rand::random::<u32>()))
+}
+
+/// A database of its own for a test, dropped at the end, for cli commands that would pull
+/// tables or rows from under the server and the other tests.
+pub struct TestDatabase {
+    name: String,
+    url: String,
+}
+
+impl TestDatabase {
+    pub fn create() -> Self {
+        let server_url = std::env::var("DATABASE_URL").unwrap();
+        let name = format!("cr8s_test_{}", rand::random::<u32>());
+        let mut connection = PgConnection::establish(&server_url).unwrap();
+        diesel::sql_query(format!("CREATE DATABASE {}", name))
+            .execute(&mut connection)
+            .unwrap();
+        let (host, _) = server_url.rsplit_once('/').unwrap();
+        let url = format!("{}/{}", host, name);
+        TestDatabase { name, url }
+    }
+
+    pub fn connect(&self) -> PgConnection {
+        PgConnection::establish(&self.url).unwrap()
+    }
+
+    /// The `cli` binary with `args`, run against this database.
+    pub fn cli_command(&self, args: &[&str]) -> Command {
+        let mut command = cli_command(args);
+        command
+            .env("DATABASE_URL", &self.url)
+            .env_remove("ROCKET_DATABASES");
+        command
+    }
+
+    pub fn run_cli(&self, args: &[&str]) -> Output {
+        let output = self.cli_command(args).output().unwrap();
+        println!("{:?}", output);
+        output
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let server_url = std::env::var("DATABASE_URL").unwrap();
+        let mut connection = PgConnection::establish(&server_url).unwrap();
+        let _ = diesel::sql_query(format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            self.name
+        ))
+        .execute(&mut connection);
+    }
 }
@@ -0,0 +1,107 @@
+use common::{run_cli_with, APP_HOST};
+use reqwest::{blocking::Client, Method, StatusCode};
+
+pub mod common;
+
+const ORIGIN: &str = "https://app.cr8s.com";
+
+fn preflight(method: &str, headers: &str) -> reqwest::blocking::Response {
+    Client::new()
+        .request(Method::OPTIONS, format!("{}/crates/1", APP_HOST))
+        .header("Origin", ORIGIN)
+        .header("Access-Control-Request-Method", method)
+        .header("Access-Control-Request-Headers", headers)
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_preflight() {
+    let response = preflight("PATCH", "authorization, content-type, if-match");
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], ORIGIN);
+    assert_eq!(headers["access-control-allow-credentials"], "true");
+    assert_eq!(headers["access-control-max-age"], "86400");
+    assert_eq!(headers["vary"], "Origin");
+    let methods = headers["access-control-allow-methods"].to_str().unwrap();
+    assert!(methods.split(", ").any(|method| method == "PATCH"));
+
+    let response = preflight("TRACE", "authorization");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(response
+        .headers()
+        .get("access-control-allow-methods")
+        .is_none());
+
+    let response = preflight("GET", "x-not-allowed");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_cors_headers_echo_the_origin() {
+    let client = common::get_client_with_logged_in_viewer();
+    let response = client
+        .get(format!("{}/crates", APP_HOST))
+        .header("Origin", ORIGIN)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], ORIGIN);
+    assert_eq!(
+        headers["access-control-expose-headers"],
+        "ETag, X-Request-Id"
+    );
+    assert_eq!(headers["vary"], "Origin");
+
+    // Origins outside the allowlist get no CORS headers
+    let response = client
+        .get(format!("{}/crates", APP_HOST))
+        .header("Origin", "https://evil.example.com")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+    assert!(response
+        .headers()
+        .get("access-control-allow-credentials")
+        .is_none());
+
+    // Requests without an origin are not cross-origin
+    let response = client.get(format!("{}/crates", APP_HOST)).send().unwrap();
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+}
+
+#[test]
+fn test_invalid_cors_settings_fail_at_startup() {
+    let output = run_cli_with(
+        &["users", "list"],
+        &[(
+            "ROCKET_CORS",
+            r#"{allowed_origins=["cr8s.com","https://*.cr8s.com"],allowed_methods=["FETCH"]}"#,
+        )],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cors.allowed_origins: 'cr8s.com' is not an origin"));
+    assert!(!stderr.contains("https://*.cr8s.com"));
+    assert!(stderr.contains("cors.allowed_methods: 'FETCH' is not an HTTP method"));
+
+    let output = run_cli_with(
+        &["users", "list"],
+        &[(
+            "ROCKET_CORS",
+            r#"{allowed_origins=["*"],allow_credentials=true}"#,
+        )],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cors.allowed_origins: '*' cannot be combined with allow_credentials"));
+}
@@ -1,5 +1,5 @@
 use common::{create_test_crate, create_test_rustacean, delete_test_crate, delete_test_rustacean};
-use reqwest::{blocking::Client, StatusCode};
+use reqwest::{blocking::Client, header, StatusCode};
 use serde_json::{json, Value};
 
 pub mod common;
@@ -75,6 +75,7 @@ fn test_create_crate() {
             "description": "Foo crate description",
             "rustacean_id": rustacean["id"],
             "created_at": a_crate["created_at"],
+            "updated_at": a_crate["updated_at"],
         })
     );
 
@@ -88,9 +89,12 @@ fn test_update_crate() {
     let rustacean = create_test_rustacean(&client);
     let rustacean2 = create_test_rustacean(&client);
     let a_crate = create_test_crate(&client, &rustacean);
+    let url = format!("{}/crates/{}", common::APP_HOST, a_crate["id"]);
+    let etag = common::get_etag(&client, &url);
 
     let response = client
-        .put(format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .put(&url)
+        .header(header::IF_MATCH, etag)
         .json(&json!({
             "code": "newcode",
             "name": "Crate new name",
@@ -121,14 +125,91 @@ fn test_update_crate() {
              pariatur.",
             "rustacean_id": rustacean2["id"],
             "created_at": a_crate["created_at"],
+            "updated_at": crate_from_response["updated_at"],
         })
     );
+    assert!(
+        common::timestamp(&crate_from_response["updated_at"])
+            > common::timestamp(&a_crate["updated_at"])
+    );
 
     delete_test_crate(&client, a_crate);
     delete_test_rustacean(&client, rustacean);
     delete_test_rustacean(&client, rustacean2);
 }
 
+#[test]
+fn test_patch_crate() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let url = format!("{}/crates/{}", common::APP_HOST, a_crate["id"]);
+    let etag = common::get_etag(&client, &url);
+
+    let response = client
+        .patch(&url)
+        .header(header::IF_MATCH, etag)
+        .json(&json!({
+            "description": "Patched description"
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG].clone();
+
+    let crate_from_response: Value = response.json().unwrap();
+    assert_eq!(
+        crate_from_response,
+        json!({
+            "id": a_crate["id"],
+            "code": a_crate["code"],
+            "name": a_crate["name"],
+            "version": a_crate["version"],
+            "description": "Patched description",
+            "rustacean_id": rustacean["id"],
+            "created_at": a_crate["created_at"],
+            "updated_at": crate_from_response["updated_at"],
+        })
+    );
+    assert!(
+        common::timestamp(&crate_from_response["updated_at"])
+            > common::timestamp(&a_crate["updated_at"])
+    );
+
+    let response = client
+        .patch(&url)
+        .header(header::IF_MATCH, etag)
+        .json(&json!({
+            "description": null
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let crate_from_response: Value = response.json().unwrap();
+    assert_eq!(crate_from_response["description"], Value::Null);
+    assert_eq!(crate_from_response["name"], a_crate["name"]);
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_patch_crate_not_found() {
+    let client = common::get_client_with_logged_in_editor();
+
+    let response = client
+        .patch(format!("{}/crates/{}", common::APP_HOST, -1))
+        .header(header::IF_MATCH, "*")
+        .json(&json!({
+            "description": "Patched description"
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[test]
 fn test_view_crate() {
     let client_with_viewer = common::get_client_with_logged_in_viewer();
@@ -137,7 +218,7 @@ fn test_view_crate() {
     let a_crate = create_test_crate(&client_with_editor, &rustacean);
 
     let response = client_with_viewer
-        .get(&format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .get(format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
         .send()
         .unwrap();
 
@@ -147,7 +228,6 @@ fn test_view_crate() {
     assert_eq!(a_crate, crate_response);
 
     delete_test_crate(&client_with_editor, a_crate);
-    delete_test_crate(&client_with_editor, crate_response);
     delete_test_rustacean(&client_with_editor, rustacean)
 }
 
@@ -170,12 +250,169 @@ fn test_delete_crate() {
     let a_crate = create_test_crate(&client, &rustacean);
 
     let response = client
-        .delete(&format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .delete(format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .header(header::IF_MATCH, "*")
         .send()
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::NO_CONTENT);
 
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_create_crate_as_viewer() {
+    let client_with_viewer = common::get_client_with_logged_in_viewer();
+    let client_with_editor = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client_with_editor);
+
+    let response = client_with_viewer
+        .post(format!("{}/crates", common::APP_HOST))
+        .json(&json!({
+            "rustacean_id": rustacean["id"],
+            "code": "foo",
+            "name": "Foo crate",
+            "version": "0.1",
+            "description": "Foo crate description"
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    delete_test_rustacean(&client_with_editor, rustacean);
+}
+
+#[test]
+fn test_update_crate_not_found() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+
+    let response = client
+        .put(format!("{}/crates/{}", common::APP_HOST, -1))
+        .header(header::IF_MATCH, "*")
+        .json(&json!({
+            "code": "newcode",
+            "name": "Crate new name",
+            "version": "0.1.1",
+            "description": "Crate new description",
+            "rustacean_id": rustacean["id"]
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_delete_crate_as_viewer() {
+    let client_with_viewer = common::get_client_with_logged_in_viewer();
+    let client_with_editor = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client_with_editor);
+    let a_crate = create_test_crate(&client_with_editor, &rustacean);
+
+    let response = client_with_viewer
+        .delete(format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    delete_test_crate(&client_with_editor, a_crate);
+    delete_test_rustacean(&client_with_editor, rustacean);
+}
+
+#[test]
+fn test_delete_crate_not_found() {
+    let client = common::get_client_with_logged_in_editor();
+
+    let response = client
+        .delete(format!("{}/crates/{}", common::APP_HOST, -1))
+        .header(header::IF_MATCH, "*")
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_view_crate_not_modified() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let url = format!("{}/crates/{}", common::APP_HOST, a_crate["id"]);
+    let etag = common::get_etag(&client, &url);
+
+    let response = client
+        .get(&url)
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_update_crate_without_if_match() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+
+    let response = client
+        .patch(format!("{}/crates/{}", common::APP_HOST, a_crate["id"]))
+        .json(&json!({
+            "description": "Patched description"
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_update_crate_with_stale_etag() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let url = format!("{}/crates/{}", common::APP_HOST, a_crate["id"]);
+    let etag = common::get_etag(&client, &url);
+
+    let response = client
+        .patch(&url)
+        .header(header::IF_MATCH, &etag)
+        .json(&json!({
+            "description": "First editor description"
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .patch(&url)
+        .header(header::IF_MATCH, &etag)
+        .json(&json!({
+            "description": "Second editor description"
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = client
+        .delete(&url)
+        .header(header::IF_MATCH, &etag)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
     delete_test_crate(&client, a_crate);
     delete_test_rustacean(&client, rustacean);
 }
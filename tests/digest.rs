@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::process::Output;
+
+use chrono::{Locale, Utc};
+use chrono_tz::Europe::Berlin;
+use common::{
+    create_test_crate, create_test_rustacean, delete_test_crate, delete_test_rustacean,
+    read_messages_to, run_cli_with, temp_mail_dir,
+};
+use reqwest::{header, StatusCode};
+
+pub mod common;
+
+fn send_digest(to: &str, mail_dir: &Path, envs: &[(&str, &str)]) -> Output {
+    let mail_envs = [
+        ("MAIL_TRANSPORT", "file"),
+        ("MAIL_FILE_DIR", mail_dir.to_str().unwrap()),
+    ];
+    run_cli_with(&["digest-send", to, "24"], &[&mail_envs, envs].concat())
+}
+
+#[test]
+fn test_digest_send_with_file_transport() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let mail_dir = temp_mail_dir();
+
+    let output = send_digest("digest@cr8s.com", &mail_dir, &[]);
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert!(output.status.success());
+    let messages = read_messages_to(&mail_dir, &["digest@cr8s.com"]);
+    assert_eq!(messages.len(), 1);
+    let (message, _) = &messages[0];
+    assert!(message.contains("From: Cr8s <info@cr8s.com>"));
+    assert!(message.contains("multipart/alternative"));
+    assert!(message.contains("Content-Type: text/plain"));
+    assert!(message.contains("Content-Type: text/html"));
+    assert!(message.contains("* Foo crate - foo 0.1.0"));
+    assert!(message.contains("<h2>Foo crate - <code>foo 0.1.0</code></h2>"));
+}
+
+#[test]
+fn test_digest_send_hides_recipients_in_bcc() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let mail_dir = temp_mail_dir();
+
+    let output = send_digest(
+        "first@cr8s.com,second@cr8s.com",
+        &mail_dir,
+        &[
+            ("MAIL_FROM", "Digest <digest@cr8s.com>"),
+            ("MAIL_REPLY_TO", "support@cr8s.com"),
+        ],
+    );
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert!(output.status.success());
+    let messages = read_messages_to(&mail_dir, &["first@cr8s.com", "second@cr8s.com"]);
+    assert_eq!(messages.len(), 1);
+    let (message, envelope) = &messages[0];
+    assert!(message.contains("From: Digest <digest@cr8s.com>"));
+    assert!(message.contains("Reply-To: support@cr8s.com"));
+    assert!(!message.contains("first@cr8s.com"));
+    assert!(!message.contains("second@cr8s.com"));
+    assert_eq!(
+        envelope["forward_path"],
+        serde_json::json!(["first@cr8s.com", "second@cr8s.com"])
+    );
+}
+
+#[test]
+fn test_digest_send_per_recipient() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let mail_dir = temp_mail_dir();
+
+    let output = send_digest(
+        "first@cr8s.com,Second <second@cr8s.com>",
+        &mail_dir,
+        &[("MAIL_DELIVERY", "per-recipient")],
+    );
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert!(output.status.success());
+    let mut messages = read_messages_to(&mail_dir, &["first@cr8s.com", "second@cr8s.com"]);
+    messages.sort_by_key(|(message, _)| message.contains("second@cr8s.com"));
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].0.contains("To: first@cr8s.com"));
+    assert!(messages[0].0.contains("Hi first@cr8s.com,"));
+    assert!(!messages[0].0.contains("second@cr8s.com"));
+    assert!(messages[1].0.contains("To: Second <second@cr8s.com>"));
+    assert!(messages[1].0.contains("Hi Second,"));
+    assert!(!messages[1].0.contains("first@cr8s.com"));
+}
+
+#[test]
+fn test_digest_preview() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+
+    let response = client
+        .get(format!("{}/admin/digest/preview", common::APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let admin_client = common::get_client_with_logged_in_admin();
+    let response = admin_client
+        .get(format!(
+            "{}/admin/digest/preview?hours_since=1",
+            common::APP_HOST
+        ))
+        .send()
+        .unwrap();
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().unwrap();
+    assert!(html.contains("<h2>Foo crate - <code>foo 0.1.0</code></h2>"));
+    assert!(html.contains("<h3>John</h3>"));
+    assert!(html.contains("updated crates of the past 1 hours."));
+}
+
+#[test]
+fn test_digest_window_is_counted_in_hours() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    // Outside a window of 1 second, inside one of 1 hour
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    let admin_client = common::get_client_with_logged_in_admin();
+    let response = admin_client
+        .get(format!(
+            "{}/admin/digest/preview?hours_since=1",
+            common::APP_HOST
+        ))
+        .send()
+        .unwrap();
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().unwrap();
+    assert!(html.contains("<h2>Foo crate - <code>foo 0.1.0</code></h2>"));
+}
+
+#[test]
+fn test_digest_send_dry_run() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let mail_dir = temp_mail_dir();
+    let output_file = temp_mail_dir().with_extension("html");
+
+    let output = run_cli_with(
+        &[
+            "digest-send",
+            "--dry-run",
+            "--output",
+            output_file.to_str().unwrap(),
+        ],
+        &[
+            ("MAIL_TRANSPORT", "file"),
+            ("MAIL_FILE_DIR", mail_dir.to_str().unwrap()),
+            ("DIGEST_TIMEZONE", "Europe/Berlin"),
+            ("DIGEST_LOCALE", "de_DE"),
+        ],
+    );
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert!(output.status.success());
+    let html = std::fs::read_to_string(&output_file).unwrap();
+    std::fs::remove_file(&output_file).unwrap();
+    assert!(html.contains("<h1>Cr8s Daily digest</h1>"));
+    assert!(html.contains("<h2>Foo crate - <code>foo 0.1.0</code></h2>"));
+    let month = Utc::now()
+        .with_timezone(&Berlin)
+        .format_localized("%B %Y", Locale::de_DE)
+        .to_string();
+    assert!(html.contains("New, published "));
+    assert!(html.contains(&month));
+    assert!(!mail_dir.exists());
+}
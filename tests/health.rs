@@ -0,0 +1,49 @@
+use common::APP_HOST;
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::{json, Value};
+
+pub mod common;
+
+#[test]
+fn test_health() {
+    let response = Client::new()
+        .get(format!("{}/health", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.json::<Value>().unwrap(), json!({ "status": "up" }));
+}
+
+#[test]
+fn test_ready() {
+    let response = Client::new()
+        .get(format!("{}/ready", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().unwrap();
+    assert_eq!(body["status"], "up");
+    for dependency in ["postgres", "redis"] {
+        assert_eq!(body["checks"][dependency]["status"], "up");
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+}
+
+#[test]
+fn test_version() {
+    let response = Client::new()
+        .get(format!("{}/version", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().unwrap();
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!body["commit"].as_str().unwrap().is_empty());
+    let latest_migration = std::fs::read_dir("migrations")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .max()
+        .unwrap();
+    let latest_version: String = latest_migration.split('_').next().unwrap().replace('-', "");
+    assert_eq!(body["migration"], latest_version);
+}
@@ -0,0 +1,44 @@
+use chrono::NaiveDateTime;
+use cr8s::scheduler::Task;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+pub mod common;
+
+#[test]
+fn test_get_jobs() {
+    let client = common::get_client_with_logged_in_admin();
+    let response = client
+        .get(format!("{}/admin/jobs", common::APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Every job reports its schedule, debug builds configure the weekly digest
+    let json: Value = response.json().unwrap();
+    let jobs = json.as_array().unwrap();
+    for job in jobs {
+        let name = job["name"].as_str().unwrap();
+        assert!(name.parse::<Task>().is_ok(), "unknown job {}", name);
+        assert!(job["schedule"].is_string());
+        assert!(job["next_run_at"].is_string());
+    }
+
+    let digest = jobs
+        .iter()
+        .find(|job| job["name"] == "digest-weekly")
+        .expect("digest-weekly job");
+    assert_eq!(digest["schedule"], "0 0 7 * * Mon");
+    let next_run_at = digest["next_run_at"].as_str().unwrap();
+    assert!(NaiveDateTime::parse_from_str(next_run_at, "%Y-%m-%dT%H:%M:%S%.f").is_ok());
+}
+
+#[test]
+fn test_get_jobs_as_editor() {
+    let client = common::get_client_with_logged_in_editor();
+    let response = client
+        .get(format!("{}/admin/jobs", common::APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
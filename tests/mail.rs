@@ -0,0 +1,28 @@
+pub mod common;
+
+#[test]
+fn test_in_memory_transport_keeps_the_rendered_messages() {
+    std::env::set_var("MAIL_TRANSPORT", "memory");
+    cr8s::config::init(&cr8s::config::figment()).unwrap();
+
+    let client = common::get_client_with_logged_in_admin();
+    let rustacean = common::create_test_rustacean(&client);
+    let a_crate = common::create_test_crate(&client, &rustacean);
+
+    cr8s::commands::send_digest(
+        vec!["memory@cr8s.com".to_string()],
+        1,
+        Some("In-memory digest".to_string()),
+    );
+
+    let messages = cr8s::mail::sent_messages();
+    let (envelope, message) = messages
+        .iter()
+        .find(|(_, message)| message.contains("Subject: In-memory digest"))
+        .unwrap();
+    assert_eq!(envelope.to()[0].to_string(), "memory@cr8s.com");
+    assert!(message.contains("Foo crate"));
+
+    common::delete_test_crate(&client, a_crate);
+    common::delete_test_rustacean(&client, rustacean);
+}
@@ -0,0 +1,58 @@
+use common::APP_HOST;
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::json;
+
+pub mod common;
+
+fn scrape() -> String {
+    let response = Client::new()
+        .get(format!("{}/metrics", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/plain; version=0.0.4"
+    );
+    response.text().unwrap()
+}
+
+/// Value of the sample with exactly these name and labels, 0 when absent.
+fn sample(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_metrics_count_requests_and_logins() {
+    let requests = r#"http_requests_total{method="GET",route="/rustaceans/<id>",status="4xx"}"#;
+    let latencies = r#"http_request_duration_seconds_count{method="GET",route="/rustaceans/<id>"}"#;
+    let failures = r#"logins_total{outcome="failure"}"#;
+    let before = scrape();
+
+    let response = Client::new()
+        .get(format!("{}/rustaceans/999999", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = Client::new()
+        .post(format!("{}/login", APP_HOST))
+        .json(&json!({ "username": "test_admin", "password": "wrong" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let after = scrape();
+    assert_eq!(sample(&after, requests), sample(&before, requests) + 1.0);
+    assert_eq!(sample(&after, latencies), sample(&before, latencies) + 1.0);
+    assert_eq!(sample(&after, failures), sample(&before, failures) + 1.0);
+    assert!(after.contains("# TYPE http_request_duration_seconds histogram"));
+    assert!(sample(&after, r#"pool_max_connections{pool="postgres"}"#) > 0.0);
+    // The Postgres pool does not expose its connections, the database counts every client
+    assert!(!after.contains(r#"pool_connections{pool="postgres""#));
+    assert!(sample(&after, r#"database_connections{state="active"}"#) >= 1.0);
+    assert!(sample(&after, r#"pool_max_connections{pool="redis"}"#) > 0.0);
+}
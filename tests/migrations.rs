@@ -0,0 +1,98 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use common::TestDatabase;
+use diesel::RunQueryDsl;
+
+pub mod common;
+
+fn db(database: &TestDatabase, subcommand: &str) -> String {
+    let output = database.run_cli(&["db", subcommand]);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn latest_migration() -> String {
+    std::fs::read_dir("migrations")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn test_db_rollback_and_migrate() {
+    let database = TestDatabase::create();
+    let latest = latest_migration();
+    db(&database, "migrate");
+    assert_eq!(db(&database, "migrate").trim(), "0 migrations applied");
+    assert!(db(&database, "status").contains(&format!("applied  {}", latest)));
+
+    let version = latest.split('_').next().unwrap().replace('-', "");
+    assert_eq!(
+        db(&database, "rollback").trim(),
+        format!("Reverted migration {}", version)
+    );
+    assert!(db(&database, "status").contains(&format!("pending  {}", latest)));
+
+    let output = db(&database, "migrate");
+    assert!(output.contains(&format!("Applied migration {}", version)));
+    assert!(db(&database, "status")
+        .lines()
+        .all(|line| line.starts_with("applied")));
+}
+
+#[test]
+fn test_migrations_wait_for_another_instance() {
+    let database = TestDatabase::create();
+    let latest = latest_migration();
+    let version = latest.split('_').next().unwrap().replace('-', "");
+
+    // Another instance holds the migration lock, as `DatabaseRepository::lock_migrations`
+    let mut connection = database.connect();
+    diesel::sql_query("SELECT pg_advisory_lock(1668429933, 0)")
+        .execute(&mut connection)
+        .unwrap();
+    let mut migrate = database
+        .cli_command(&["db", "migrate"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_secs(5));
+    assert!(migrate.try_wait().unwrap().is_none());
+
+    diesel::sql_query("SELECT pg_advisory_unlock(1668429933, 0)")
+        .execute(&mut connection)
+        .unwrap();
+    let output = migrate.wait_with_output().unwrap();
+    println!("{:?}", output);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(&format!("Applied migration {}", version)));
+}
+
+#[test]
+fn test_rollback_waits_for_another_instance() {
+    let database = TestDatabase::create();
+    db(&database, "migrate");
+
+    let mut connection = database.connect();
+    diesel::sql_query("SELECT pg_advisory_lock(1668429933, 0)")
+        .execute(&mut connection)
+        .unwrap();
+    let mut rollback = database
+        .cli_command(&["db", "rollback"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_secs(5));
+    assert!(rollback.try_wait().unwrap().is_none());
+
+    diesel::sql_query("SELECT pg_advisory_unlock(1668429933, 0)")
+        .execute(&mut connection)
+        .unwrap();
+    let output = rollback.wait_with_output().unwrap();
+    println!("{:?}", output);
+    assert!(output.status.success());
+}
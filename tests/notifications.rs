@@ -0,0 +1,198 @@
+use common::{
+    create_test_crate, delete_test_crate, delete_test_rustacean, read_messages_to, run_cli,
+    run_cli_with, temp_mail_dir, APP_HOST,
+};
+use reqwest::{blocking::Client, header, StatusCode};
+use serde_json::{json, Value};
+
+pub mod common;
+
+fn create_rustacean_with_email(client: &Client, email: &str) -> Value {
+    let response = client
+        .post(format!("{}/rustaceans", APP_HOST))
+        .json(&json!({ "name": "Jane", "email": email }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    response.json().unwrap()
+}
+
+fn patch_crate(client: &Client, a_crate: &Value, patch: Value) {
+    let response = client
+        .patch(format!("{}/crates/{}", APP_HOST, a_crate["id"]))
+        .header(header::IF_MATCH, "*")
+        .json(&patch)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+fn save_preferences(client: &Client, preferences: Value) -> Value {
+    let response = client
+        .put(format!("{}/me/notifications", APP_HOST))
+        .json(&preferences)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.json().unwrap()
+}
+
+/// Delivers the emails to `email`, leaving those of other tests pending, and confirms it
+/// through the link of the verification email.
+fn verify_email(email: &str) {
+    let mail_dir = temp_mail_dir();
+    let output = run_cli_with(
+        &["outbox", "deliver", "--to", email],
+        &[
+            ("MAIL_TRANSPORT", "file"),
+            ("MAIL_FILE_DIR", mail_dir.to_str().unwrap()),
+        ],
+    );
+    assert!(output.status.success());
+
+    let messages = read_messages_to(&mail_dir, &[email]);
+    let (message, _) = messages
+        .iter()
+        .rev()
+        .find(|(message, _)| message.contains("Confirm your notification email"))
+        .unwrap();
+    let message = message.replace("=\r\n", "").replace("=3D", "=");
+    let start = message.find("/notifications/verify?token=").unwrap()
+        + "/notifications/verify?token=".len();
+    let token: String = message[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '.')
+        .collect();
+
+    // Following the link only asks for a confirmation
+    let url = format!("{}/notifications/verify?token={}", APP_HOST, token);
+    let response = Client::new().get(&url).send().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().unwrap().contains("method=\"post\""));
+
+    let response = Client::new().post(&url).send().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<Value>().unwrap(),
+        json!({ "verified": true })
+    );
+}
+
+/// Returns the subjects of the pending emails queued for `recipient`.
+fn pending_subjects(recipient: &str) -> Vec<String> {
+    let output = run_cli(&["outbox", "list", "--status", "pending", "--limit", "100"]);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains(&format!("to={} ", recipient)))
+        .filter_map(|line| {
+            line.split_once("subject=")
+                .map(|(_, subject)| subject.to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn test_owner_is_notified_of_changes_by_others() {
+    let client = common::get_client_with_logged_in_editor();
+    let email = format!("owner_{}@cr8s.com", rand::random::<u32>());
+    let rustacean = create_rustacean_with_email(&client, &email);
+    let a_crate = create_test_crate(&client, &rustacean);
+
+    patch_crate(&client, &a_crate, json!({ "version": "0.2.0" }));
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    let subjects = pending_subjects(&email);
+    assert_eq!(
+        subjects,
+        vec![
+            "\"Your crate foo was updated\"",
+            "\"Your crate foo was deleted\"",
+        ]
+    );
+}
+
+#[test]
+fn test_owner_is_not_notified_when_opted_out_or_editing() {
+    let client = common::get_client_with_logged_in_editor();
+    let email = format!("owner_{}@cr8s.com", rand::random::<u32>());
+    let rustacean = create_rustacean_with_email(&client, &email);
+    let a_crate = create_test_crate(&client, &rustacean);
+
+    // Claiming the email without verifying it does not stop notifications
+    let owner_client = common::get_client_with_logged_in_viewer();
+    let preferences = save_preferences(
+        &owner_client,
+        json!({ "email": email.to_uppercase(), "crate_updated": false }),
+    );
+    assert_eq!(
+        preferences,
+        json!({
+            "email": email.to_uppercase(),
+            "crate_updated": false,
+            "crate_deleted": true,
+            "email_verified_at": null,
+        })
+    );
+    patch_crate(&client, &a_crate, json!({ "version": "0.2.0" }));
+    assert_eq!(
+        pending_subjects(&email),
+        vec!["\"Your crate foo was updated\""]
+    );
+
+    // The owner verified the email and opted out of updates
+    verify_email(&email.to_uppercase());
+    let response = owner_client
+        .get(format!("{}/me/notifications", APP_HOST))
+        .send()
+        .unwrap();
+    assert!(response.json::<Value>().unwrap()["email_verified_at"].is_string());
+    patch_crate(&client, &a_crate, json!({ "version": "0.3.0" }));
+    assert_eq!(
+        pending_subjects(&email),
+        vec!["\"Your crate foo was updated\""]
+    );
+    save_preferences(
+        &owner_client,
+        json!({ "email": "viewer@cr8s.com", "crate_updated": true }),
+    );
+
+    // The owner edits their own crate
+    save_preferences(&client, json!({ "email": email }));
+    verify_email(&email);
+    patch_crate(&client, &a_crate, json!({ "version": "0.4.0" }));
+    delete_test_crate(&client, a_crate);
+    assert!(pending_subjects(&email).is_empty());
+    save_preferences(&client, json!({ "email": "editor@cr8s.com" }));
+
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_notification_preferences_validation() {
+    let client = common::get_client_with_logged_in_viewer();
+    let response = client
+        .put(format!("{}/me/notifications", APP_HOST))
+        .json(&json!({ "email": "not an email" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = Client::new()
+        .get(format!("{}/me/notifications", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    for token in ["garbage", "1.00", "1.4102444800.00"] {
+        let response = Client::new()
+            .post(format!("{}/notifications/verify?token={}", APP_HOST, token))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,84 @@
+use common::{
+    create_test_crate, create_test_rustacean, delete_test_crate, delete_test_rustacean,
+    read_messages, run_cli, run_cli_with, temp_mail_dir,
+};
+use serde_json::json;
+
+pub mod common;
+
+/// Returns the `outbox list` line of the email sent to `recipient`.
+fn find_listed(status: &str, recipient: &str) -> Option<String> {
+    let output = run_cli(&["outbox", "list", "--status", status, "--limit", "100"]);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find(|line| line.contains(&format!("to={} ", recipient)))
+        .map(String::from)
+}
+
+#[test]
+fn test_failed_delivery_is_retried_after_requeue() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    let recipient = format!("outbox_{}@cr8s.com", rand::random::<u32>());
+
+    // Nothing listens on the SMTP port, the email stays queued instead of failing the command
+    let output = run_cli_with(
+        &["digest-send", &recipient, "24"],
+        &[
+            ("MAIL_TRANSPORT", "smtp"),
+            ("SMTP_HOST", "127.0.0.1"),
+            ("SMTP_USERNAME", "cr8s"),
+            ("SMTP_PASSWORD", "cr8s"),
+        ],
+    );
+
+    delete_test_crate(&client, a_crate);
+    delete_test_rustacean(&client, rustacean);
+
+    assert!(output.status.success());
+    let pending = find_listed("pending", &recipient).unwrap();
+    assert!(pending.contains(" attempts=1 "));
+    let id = pending
+        .trim_start_matches('#')
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_string();
+
+    let output = run_cli(&["outbox", "requeue", &id]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("Requeued 1 emails"));
+
+    let mail_dir = temp_mail_dir();
+    let output = run_cli_with(
+        &["outbox", "deliver"],
+        &[
+            ("MAIL_TRANSPORT", "file"),
+            ("MAIL_FILE_DIR", mail_dir.to_str().unwrap()),
+        ],
+    );
+    assert!(output.status.success());
+
+    let messages: Vec<_> = read_messages(&mail_dir)
+        .into_iter()
+        .filter(|(_, envelope)| envelope["forward_path"] == json!([recipient]))
+        .collect();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].0.contains("* Foo crate - foo 0.1.0"));
+    assert!(find_listed("pending", &recipient).is_none());
+    assert!(find_listed("sent", &recipient).is_some());
+}
+
+#[test]
+fn test_requeue_unknown_email() {
+    let output = run_cli(&["outbox", "requeue", "0"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("Requeued 0 emails"));
+}
@@ -0,0 +1,99 @@
+use common::APP_HOST;
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::{json, Value};
+
+pub mod common;
+
+fn set_viewer_permissions(client: &Client, permissions: Value) -> reqwest::blocking::Response {
+    client
+        .put(format!("{}/roles/viewer/permissions", APP_HOST))
+        .json(&permissions)
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_role_permissions_apply_to_open_sessions() {
+    let admin = common::get_client_with_logged_in_admin();
+    let viewer = common::get_client_with_logged_in_viewer();
+
+    let response = viewer
+        .post(format!("{}/rustaceans", APP_HOST))
+        .json(&json!({ "name": "Foo bar", "email": "foo@bar.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = set_viewer_permissions(&admin, json!(["rustacean:create"]));
+    assert_eq!(response.status(), StatusCode::OK);
+    let role_permissions: Value = response.json().unwrap();
+    assert_eq!(
+        role_permissions,
+        json!({ "role": "viewer", "permissions": ["rustacean:create"] })
+    );
+
+    let response = viewer
+        .post(format!("{}/rustaceans", APP_HOST))
+        .json(&json!({ "name": "Foo bar", "email": "foo@bar.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let rustacean: Value = response.json().unwrap();
+
+    let response = set_viewer_permissions(&admin, json!([]));
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = viewer
+        .post(format!("{}/rustaceans", APP_HOST))
+        .json(&json!({ "name": "Foo bar", "email": "foo@bar.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    common::delete_test_rustacean(&admin, rustacean);
+}
+
+#[test]
+fn test_role_permissions_validation() {
+    let admin = common::get_client_with_logged_in_admin();
+
+    let response = admin
+        .get(format!("{}/roles/editor/permissions", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let role_permissions: Value = response.json().unwrap();
+    assert!(role_permissions["permissions"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("crate:create")));
+
+    let response = admin
+        .get(format!("{}/permissions", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let permissions: Vec<Value> = response.json().unwrap();
+    assert!(permissions
+        .iter()
+        .any(|permission| permission["code"] == "user:manage"));
+
+    let response = set_viewer_permissions(&admin, json!(["crate:fly"]));
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = admin
+        .put(format!("{}/roles/admin/permissions", APP_HOST))
+        .json(&json!(["crate:create"]))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = admin
+        .get(format!("{}/roles/owner/permissions", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let editor = common::get_client_with_logged_in_editor();
+    let response = set_viewer_permissions(&editor, json!(["crate:create"]));
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
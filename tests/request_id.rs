@@ -0,0 +1,48 @@
+use common::{delete_test_rustacean, APP_HOST};
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::{json, Value};
+
+pub mod common;
+
+#[test]
+fn test_request_id_is_echoed() {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/crates/999999", APP_HOST))
+        .header("X-Request-Id", "lb-7f3a.42")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["x-request-id"], "lb-7f3a.42");
+    let body: Value = response.json().unwrap();
+    assert_eq!(body["request_id"], "lb-7f3a.42");
+
+    // Ids unsafe to log are replaced by a generated one
+    let response = client
+        .get(format!("{}/health", APP_HOST))
+        .header("X-Request-Id", "bad id\"")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert_eq!(request_id.len(), 32);
+    assert!(request_id.chars().all(|c| c.is_ascii_alphanumeric()));
+}
+
+#[test]
+fn test_too_long_request_id_is_replaced_on_mutations() {
+    let client = common::get_client_with_logged_in_editor();
+    let long_id = "a".repeat(100);
+    let response = client
+        .post(format!("{}/rustaceans", APP_HOST))
+        .header("X-Request-Id", &long_id)
+        .json(&json!({ "name": "John", "email": "j.doe@gmail.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert_ne!(request_id, long_id);
+    assert_eq!(request_id.len(), 32);
+
+    delete_test_rustacean(&client, response.json().unwrap());
+}
@@ -0,0 +1,33 @@
+use common::{run_cli, APP_HOST};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+pub mod common;
+
+#[test]
+fn test_get_roles() {
+    let client = common::get_client_with_logged_in_admin();
+    let response = client.get(format!("{}/roles", APP_HOST)).send().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let roles: Vec<Value> = response.json().unwrap();
+    let codes: Vec<&str> = roles
+        .iter()
+        .map(|role| role["code"].as_str().unwrap())
+        .collect();
+    for code in ["admin", "editor", "viewer"] {
+        assert!(codes.contains(&code));
+    }
+
+    let client = common::get_client_with_logged_in_editor();
+    let response = client.get(format!("{}/roles", APP_HOST)).send().unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_create_user_with_unknown_role_fails() {
+    let output = run_cli(&["users", "create", "test_unknown_role", "1234", "bogus"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unknown role code 'bogus', expected one of admin, editor, viewer"));
+    assert!(!stderr.contains("panicked"));
+}
@@ -1,4 +1,4 @@
-use reqwest::{blocking::Client, StatusCode};
+use reqwest::{blocking::Client, header, StatusCode};
 use rocket::serde::json::{json, Value};
 
 use crate::common::{create_test_rustacean, delete_test_rustacean};
@@ -64,7 +64,8 @@ fn test_create_rustacean() {
             "id": rustacean["id"],
             "name":"John",
             "email":"j.doe@gmail.com",
-            "created_at": rustacean["created_at"]
+            "created_at": rustacean["created_at"],
+            "updated_at": rustacean["updated_at"]
         })
     );
 
@@ -95,7 +96,8 @@ fn test_view_rustacean() {
             "id": rustacean["id"],
             "name":"John",
             "email":"j.doe@gmail.com",
-            "created_at": rustacean["created_at"]
+            "created_at": rustacean["created_at"],
+            "updated_at": rustacean["updated_at"]
         })
     );
 
@@ -117,13 +119,12 @@ fn test_view_rustacean_not_found() {
 fn test_update_rustacean() {
     let client = common::get_client_with_logged_in_editor();
     let rustacean: Value = create_test_rustacean(&client);
+    let url = format!("{}/rustaceans/{}", common::APP_HOST, rustacean["id"]);
+    let etag = common::get_etag(&client, &url);
 
     let response = client
-        .put(format!(
-            "{}/rustaceans/{}",
-            common::APP_HOST,
-            rustacean["id"]
-        ))
+        .put(&url)
+        .header(header::IF_MATCH, etag)
         .json(&json!({
             "name":"Gunrock",
             "email":"gunrock@gmail.com"
@@ -132,17 +133,57 @@ fn test_update_rustacean() {
         .unwrap();
     assert_eq!(response.status(), StatusCode::OK);
 
-    let rustacean: Value = response.json().unwrap();
+    let updated: Value = response.json().unwrap();
 
     assert_eq!(
-        rustacean,
+        updated,
         json!({
             "id": rustacean["id"],
             "name":"Gunrock",
             "email":"gunrock@gmail.com",
-            "created_at": rustacean["created_at"]
+            "created_at": rustacean["created_at"],
+            "updated_at": updated["updated_at"]
         })
     );
+    assert!(
+        common::timestamp(&updated["updated_at"]) > common::timestamp(&rustacean["updated_at"])
+    );
+
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_patch_rustacean() {
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean: Value = create_test_rustacean(&client);
+    let url = format!("{}/rustaceans/{}", common::APP_HOST, rustacean["id"]);
+    let etag = common::get_etag(&client, &url);
+
+    let response = client
+        .patch(&url)
+        .header(header::IF_MATCH, etag)
+        .json(&json!({
+            "email":"john@gmail.com"
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let updated: Value = response.json().unwrap();
+
+    assert_eq!(
+        updated,
+        json!({
+            "id": rustacean["id"],
+            "name":"John",
+            "email":"john@gmail.com",
+            "created_at": rustacean["created_at"],
+            "updated_at": updated["updated_at"]
+        })
+    );
+    assert!(
+        common::timestamp(&updated["updated_at"]) > common::timestamp(&rustacean["updated_at"])
+    );
 
     delete_test_rustacean(&client, rustacean);
 }
@@ -158,7 +199,74 @@ fn test_delete_rustacean() {
             common::APP_HOST,
             rustacean["id"]
         ))
+        .header(header::IF_MATCH, "*")
         .send()
         .unwrap();
     assert_eq!(response.status(), StatusCode::NO_CONTENT);
 }
+
+#[test]
+fn test_create_rustacean_as_viewer() {
+    let client = common::get_client_with_logged_in_viewer();
+
+    let response = client
+        .post(format!("{}/rustaceans", common::APP_HOST))
+        .json(&json!({
+            "name":"John",
+            "email":"j.doe@gmail.com"
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_update_rustacean_not_found() {
+    let client = common::get_client_with_logged_in_editor();
+
+    let response = client
+        .put(format!("{}/rustaceans/{}", common::APP_HOST, -1))
+        .header(header::IF_MATCH, "*")
+        .json(&json!({
+            "name":"Gunrock",
+            "email":"gunrock@gmail.com"
+        }))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_delete_rustacean_as_viewer() {
+    let client_with_viewer = common::get_client_with_logged_in_viewer();
+    let client_with_editor = common::get_client_with_logged_in_editor();
+    let rustacean: Value = create_test_rustacean(&client_with_editor);
+
+    let response = client_with_viewer
+        .delete(format!(
+            "{}/rustaceans/{}",
+            common::APP_HOST,
+            rustacean["id"]
+        ))
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    delete_test_rustacean(&client_with_editor, rustacean);
+}
+
+#[test]
+fn test_delete_rustacean_not_found() {
+    let client = common::get_client_with_logged_in_editor();
+
+    let response = client
+        .delete(format!("{}/rustaceans/{}", common::APP_HOST, -1))
+        .header(header::IF_MATCH, "*")
+        .send()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,34 @@
+use common::{run_cli, APP_HOST};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+pub mod common;
+
+fn seed(profile: &str) -> String {
+    let output = run_cli(&["db", "seed", "--profile", profile]);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_seed_is_idempotent() {
+    seed("test");
+    assert_eq!(
+        seed("test").trim(),
+        "Seeded test profile: 0 created, 5 already present"
+    );
+
+    let client = common::get_client_with_logged_in_viewer();
+    let response = client
+        .get(format!("{}/crates?limit=10000", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let crates: Vec<Value> = response.json().unwrap();
+    let seeded: Vec<&Value> = crates
+        .iter()
+        .filter(|a_crate| a_crate["code"] == "test_crate")
+        .collect();
+    assert_eq!(seeded.len(), 1);
+    assert_eq!(seeded[0]["version"], "1.0.0");
+}
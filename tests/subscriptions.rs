@@ -0,0 +1,210 @@
+use common::{
+    create_test_crate, create_test_rustacean, delete_test_crate, delete_test_rustacean,
+    read_messages, run_cli_with, temp_mail_dir, APP_HOST,
+};
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::{json, Value};
+
+pub mod common;
+
+fn create_test_subscription(client: &Client, body: Value) -> Value {
+    let response = client
+        .post(format!("{}/me/subscriptions", APP_HOST))
+        .json(&body)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    response.json().unwrap()
+}
+
+fn get_subscriptions(client: &Client) -> Vec<Value> {
+    let response = client
+        .get(format!("{}/me/subscriptions", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.json().unwrap()
+}
+
+/// Extracts the unsubscribe token from a message, undoing quoted-printable encoding.
+fn unsubscribe_token(message: &str) -> String {
+    let message = message.replace("=\r\n", "").replace("=3D", "=");
+    let start = message.find("/unsubscribe?token=").unwrap() + "/unsubscribe?token=".len();
+    message[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '.')
+        .collect()
+}
+
+#[test]
+fn test_subscriptions() {
+    let client = common::get_client_with_logged_in_viewer();
+    let subscription = create_test_subscription(
+        &client,
+        json!({ "email": "viewer@cr8s.com", "frequency": "weekly", "keyword": " serde " }),
+    );
+
+    assert_eq!(
+        subscription,
+        json!({
+            "id": subscription["id"],
+            "email": "viewer@cr8s.com",
+            "frequency": "weekly",
+            "rustacean_id": null,
+            "keyword": "serde",
+            "last_sent_at": null,
+            "created_at": subscription["created_at"],
+        })
+    );
+    assert!(get_subscriptions(&client).contains(&subscription));
+
+    // Subscriptions of another user are out of reach
+    let other_client = common::get_client_with_logged_in_editor();
+    assert!(!get_subscriptions(&other_client).contains(&subscription));
+    let response = other_client
+        .delete(format!(
+            "{}/me/subscriptions/{}",
+            APP_HOST, subscription["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client
+        .delete(format!(
+            "{}/me/subscriptions/{}",
+            APP_HOST, subscription["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!get_subscriptions(&client).contains(&subscription));
+}
+
+#[test]
+fn test_create_subscription_validation() {
+    let response = Client::new()
+        .get(format!("{}/me/subscriptions", APP_HOST))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let client = common::get_client_with_logged_in_viewer();
+    for body in [
+        json!({ "email": "not an email", "frequency": "daily" }),
+        json!({ "email": "viewer@cr8s.com", "frequency": "hourly" }),
+        json!({ "email": "viewer@cr8s.com", "frequency": "daily", "rustacean_id": 0 }),
+    ] {
+        let response = client
+            .post(format!("{}/me/subscriptions", APP_HOST))
+            .json(&body)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[test]
+fn test_unsubscribe_with_invalid_token() {
+    let client = Client::new();
+    for token in ["garbage", "1.00", "1.4102444800.zz", "1.4102444800.00"] {
+        let response = client
+            .get(format!("{}/unsubscribe?token={}", APP_HOST, token))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .post(format!("{}/unsubscribe?token={}", APP_HOST, token))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[test]
+fn test_digest_send_to_subscribers() {
+    let editor_client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&editor_client);
+    let a_crate = create_test_crate(&editor_client, &rustacean);
+
+    let client = common::get_client_with_logged_in_viewer();
+    let email = format!("subscriber_{}@cr8s.com", rand::random::<u32>());
+    let matching = create_test_subscription(
+        &client,
+        json!({ "email": email, "frequency": "daily", "rustacean_id": rustacean["id"], "keyword": "FOO" }),
+    );
+    let not_matching = create_test_subscription(
+        &client,
+        json!({ "email": email, "frequency": "daily", "keyword": "no crate has this keyword" }),
+    );
+
+    let mail_dir = temp_mail_dir();
+    let output = run_cli_with(
+        &["digest-send", "--subscribers", "--frequency", "daily"],
+        &[
+            ("MAIL_TRANSPORT", "file"),
+            ("MAIL_FILE_DIR", mail_dir.to_str().unwrap()),
+        ],
+    );
+
+    delete_test_crate(&editor_client, a_crate);
+
+    assert!(output.status.success());
+    let messages: Vec<_> = read_messages(&mail_dir)
+        .into_iter()
+        .filter(|(_, envelope)| envelope["forward_path"] == json!([email]))
+        .collect();
+    assert_eq!(messages.len(), 1);
+    let (message, _) = &messages[0];
+    assert!(message.contains("* Foo crate - foo 0.1.0"));
+
+    let sent = get_subscriptions(&client)
+        .into_iter()
+        .find(|subscription| subscription["id"] == matching["id"])
+        .unwrap();
+    assert!(sent["last_sent_at"].is_string());
+
+    assert!(message.contains("List-Unsubscribe: <http"));
+    assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+
+    // Following the link only asks for a confirmation
+    let token = unsubscribe_token(message);
+    let response = Client::new()
+        .get(format!("{}/unsubscribe?token={}", APP_HOST, token))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().unwrap().contains("method=\"post\""));
+    assert!(get_subscriptions(&client)
+        .iter()
+        .any(|subscription| subscription["id"] == matching["id"]));
+
+    let response = Client::new()
+        .post(format!("{}/unsubscribe?token={}", APP_HOST, token))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let ids: Vec<_> = get_subscriptions(&client)
+        .into_iter()
+        .map(|subscription| subscription["id"].clone())
+        .collect();
+    assert!(!ids.contains(&matching["id"]));
+    assert!(ids.contains(&not_matching["id"]));
+
+    let response = client
+        .delete(format!(
+            "{}/me/subscriptions/{}",
+            APP_HOST, not_matching["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    delete_test_rustacean(&editor_client, rustacean);
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use common::{
+    create_test_crate, create_test_rustacean, create_test_user, delete_test_crate,
+    delete_test_rustacean, delete_test_user, run_cli_with, APP_HOST,
+};
+use hmac::{Hmac, Mac};
+use reqwest::{blocking::Client, StatusCode};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+pub mod common;
+
+/// A request received by the stand-in endpoint, with lowercase header names.
+struct ReceivedRequest {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// Serves HTTP on a local port, answering each request with the next of `statuses`.
+fn start_endpoint(statuses: Vec<u16>) -> (String, Receiver<ReceivedRequest>) {
+    start_endpoint_with(statuses, Duration::ZERO)
+}
+
+/// Like `start_endpoint`, reporting each request as soon as it is read and answering it
+/// after `delay`.
+fn start_endpoint_with(statuses: Vec<u16>, delay: Duration) -> (String, Receiver<ReceivedRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(": ") {
+                    headers.insert(name.to_lowercase(), value.to_string());
+                }
+            }
+            let length = headers["content-length"].parse().unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            sender
+                .send(ReceivedRequest {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                })
+                .unwrap();
+            std::thread::sleep(delay);
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {} Stand-in\r\nLocation: /elsewhere\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+fn deliver_webhooks() {
+    deliver_webhooks_with(&[]);
+}
+
+fn deliver_webhooks_with(envs: &[(&str, &str)]) {
+    let output = run_cli_with(&["webhooks", "deliver"], envs);
+    assert!(output.status.success());
+}
+
+fn create_webhook(client: &Client, url: &str) -> Value {
+    let response = client
+        .post(format!("{}/webhooks", APP_HOST))
+        .json(&json!({ "url": url, "events": ["rustacean.created"] }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    response.json().unwrap()
+}
+
+fn get_deliveries(client: &Client, webhook: &Value) -> Vec<Value> {
+    let response = client
+        .get(format!(
+            "{}/webhooks/{}/deliveries",
+            APP_HOST, webhook["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    response.json().unwrap()
+}
+
+fn delete_webhook(client: &Client, webhook: &Value) {
+    let response = client
+        .delete(format!("{}/webhooks/{}", APP_HOST, webhook["id"]))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[test]
+fn test_webhook_delivery_is_signed_and_redelivered() {
+    let (url, received) = start_endpoint(vec![500, 200]);
+    let admin_client = common::get_client_with_logged_in_admin();
+    let response = admin_client
+        .post(format!("{}/webhooks", APP_HOST))
+        .json(&json!({ "url": url, "events": ["crate.created"], "secret": "s3cret" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let webhook: Value = response.json().unwrap();
+    assert_eq!(webhook["secret"], "s3cret");
+    assert_eq!(webhook["events"], json!(["crate.created"]));
+
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+    let a_crate = create_test_crate(&client, &rustacean);
+    delete_test_crate(&client, a_crate.clone());
+    delete_test_rustacean(&client, rustacean);
+
+    // The endpoint fails the first call, the delivery stays queued for a retry
+    deliver_webhooks();
+    let failed = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    let deliveries = get_deliveries(&admin_client, &webhook);
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["event"], "crate.created");
+    assert_eq!(deliveries[0]["status"], "pending");
+    assert_eq!(deliveries[0]["attempts"], 1);
+    assert_eq!(deliveries[0]["response_status"], 500);
+
+    let response = admin_client
+        .post(format!(
+            "{}/webhooks/{}/deliveries/{}/redeliver",
+            APP_HOST, webhook["id"], deliveries[0]["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    deliver_webhooks();
+    let request = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(request.body, failed.body);
+    assert_eq!(request.headers["x-cr8s-event"], "crate.created");
+    assert_eq!(
+        request.headers["x-cr8s-delivery"],
+        deliveries[0]["id"].to_string()
+    );
+
+    let timestamp: i64 = request.headers["x-cr8s-timestamp"].parse().unwrap();
+    assert!((chrono::Utc::now().timestamp() - timestamp).abs() < 60);
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+    mac.update(format!("{}.{}", timestamp, request.body).as_bytes());
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(request.headers["x-cr8s-signature"], signature);
+
+    let payload: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(payload["event"], "crate.created");
+    assert_eq!(payload["data"], a_crate);
+    assert!(payload["previous"].is_null());
+
+    let deliveries = get_deliveries(&admin_client, &webhook);
+    assert_eq!(deliveries[0]["status"], "sent");
+    assert_eq!(deliveries[0]["response_status"], 200);
+
+    delete_webhook(&admin_client, &webhook);
+}
+
+#[test]
+fn test_webhook_call_holds_no_lock_on_its_delivery() {
+    let (url, received) = start_endpoint_with(vec![200], Duration::from_secs(5));
+    let admin_client = common::get_client_with_logged_in_admin();
+    let webhook = create_webhook(&admin_client, &url);
+
+    let client = common::get_client_with_logged_in_editor();
+    let rustacean = create_test_rustacean(&client);
+
+    // While the endpoint holds the call, the webhook and its delivery can go at once
+    let mut worker = common::cli_command(&["webhooks", "deliver"])
+        .spawn()
+        .unwrap();
+    received.recv_timeout(Duration::from_secs(60)).unwrap();
+    let started = Instant::now();
+    delete_webhook(&admin_client, &webhook);
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert!(worker.wait().unwrap().success());
+
+    delete_test_rustacean(&client, rustacean);
+}
+
+#[test]
+fn test_webhooks_require_admin_and_valid_settings() {
+    let client = common::get_client_with_logged_in_editor();
+    let response = client.get(format!("{}/webhooks", APP_HOST)).send().unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let admin_client = common::get_client_with_logged_in_admin();
+    for body in [
+        json!({ "url": "not a url" }),
+        json!({ "url": "ftp://127.0.0.1/hook" }),
+        json!({ "url": "http://127.0.0.1/hook", "events": ["crate.renamed"] }),
+        json!({ "url": "http://127.0.0.1/hook", "secret": "s".repeat(129) }),
+    ] {
+        let response = admin_client
+            .post(format!("{}/webhooks", APP_HOST))
+            .json(&body)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    // A generated secret is shown once, then the webhook is updated without it
+    let response = admin_client
+        .post(format!("{}/webhooks", APP_HOST))
+        .json(&json!({ "url": "http://127.0.0.1:9/hook" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let webhook: Value = response.json().unwrap();
+    assert_eq!(webhook["secret"].as_str().unwrap().len(), 40);
+    assert_eq!(webhook["events"], json!([]));
+
+    let response = admin_client
+        .put(format!("{}/webhooks/{}", APP_HOST, webhook["id"]))
+        .json(&json!({ "url": "https://example.com/hook", "events": ["rustacean.deleted"], "active": false }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let updated: Value = response.json().unwrap();
+    assert_eq!(
+        updated,
+        json!({
+            "id": webhook["id"],
+            "url": "https://example.com/hook",
+            "events": ["rustacean.deleted"],
+            "active": false,
+            "created_at": webhook["created_at"],
+        })
+    );
+
+    delete_webhook(&admin_client, &webhook);
+    let response = admin_client
+        .get(format!("{}/webhooks/{}", APP_HOST, webhook["id"]))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_webhooks_do_not_follow_redirects_or_reach_private_hosts() {
+    let (url, received) = start_endpoint(vec![307]);
+    let admin_client = common::get_client_with_logged_in_admin();
+    let webhook = create_webhook(&admin_client, &url);
+    let client = common::get_client_with_logged_in_editor();
+
+    // Only development builds may call local endpoints
+    let rustacean = create_test_rustacean(&client);
+    deliver_webhooks_with(&[("ROCKET_WEBHOOKS", "{allow_private_hosts=false}")]);
+    let deliveries = get_deliveries(&admin_client, &webhook);
+    assert_eq!(deliveries[0]["status"], "pending");
+    assert!(deliveries[0]["response_status"].is_null());
+    assert!(deliveries[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("not a public address"));
+    assert!(received.try_recv().is_err());
+
+    // A redirect is a failed delivery, not a call to its location
+    let response = admin_client
+        .post(format!(
+            "{}/webhooks/{}/deliveries/{}/redeliver",
+            APP_HOST, webhook["id"], deliveries[0]["id"]
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    deliver_webhooks();
+    received.recv_timeout(Duration::from_secs(10)).unwrap();
+    let deliveries = get_deliveries(&admin_client, &webhook);
+    assert_eq!(deliveries[0]["status"], "pending");
+    assert_eq!(deliveries[0]["response_status"], 307);
+
+    delete_test_rustacean(&client, rustacean);
+    delete_webhook(&admin_client, &webhook);
+}
+
+#[test]
+fn test_cli_mutations_are_published() {
+    let admin_client = common::get_client_with_logged_in_admin();
+    let response = admin_client
+        .post(format!("{}/webhooks", APP_HOST))
+        .json(&json!({ "url": "https://example.com/hook", "events": ["user.created", "user.deleted"] }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let webhook: Value = response.json().unwrap();
+
+    let username = format!("test_webhooks_{}", rand::random::<u32>());
+    let output = create_test_user(&username, "1234");
+    assert!(output.status.success());
+    delete_test_user(String::from_utf8(output.stdout).unwrap());
+
+    let deliveries = get_deliveries(&admin_client, &webhook);
+    delete_webhook(&admin_client, &webhook);
+
+    let mut events: Vec<&str> = deliveries
+        .iter()
+        .filter(|delivery| delivery["payload"]["data"]["username"] == username.as_str())
+        .map(|delivery| delivery["event"].as_str().unwrap())
+        .collect();
+    events.sort_unstable();
+    assert_eq!(events, ["user.created", "user.deleted"]);
+}